@@ -19,4 +19,7 @@ pub const MOTD: &str = "Have fun. ♥";
 pub const WINDOW_SIZE_WIDTH: u32 = 640;
 pub const WINDOW_SIZE_HEIGHT: u32 = 512;
 
-pub const DARKMODE_DEFAULT: bool = true;
\ No newline at end of file
+pub const DARKMODE_DEFAULT: bool = true;
+
+pub const MAX_TRIGGERS_PER_BLOCK: usize = 128;
+pub const MAX_MIDI_EVENTS_PER_BLOCK: usize = 512;
\ No newline at end of file
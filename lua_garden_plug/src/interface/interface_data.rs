@@ -1,20 +1,31 @@
 use std::collections::BTreeMap;
 
 use super::InterfaceMode;
-use crate::{ runtime::{library, module_content::ModuleContent, parameter::Parameter, runtime_data::RuntimeState, workspace::Workspace}, RuntimeData };
+use crate::{ runtime::{crossfade, library, module, module_content::ModuleContent, parameter::Parameter, preset::PresetBank, runtime_data::RuntimeState, user_library::UserLibrary, watchdog, workspace::Workspace}, RuntimeData };
 
 
 #[derive(Clone)]
 pub struct InterfaceData {
     pub mode: InterfaceMode,
     pub workspace: Option<Workspace>,
+    pub workspace_watch: bool,
     pub draft_content: ModuleContent,
+    // Folder of lua files every module can require.
+    pub library_path: String,
+    // Scanned on this side, before each load.
+    pub user_library: Option<UserLibrary>,
 
     pub runtime_target_state: RuntimeState,
     pub runtime_clip: bool,
     pub runtime_input_noise: bool,
+    pub runtime_watchdog_budget: f32,
+    pub runtime_memory_limit: usize,
+    pub runtime_crossfade_ms: f32,
+    pub runtime_triggers: u32,
 
     pub parameters: BTreeMap<String, Parameter>,
+    // Presets of draft modules, by module name. Workspaces keep their own.
+    pub draft_presets: BTreeMap<String, PresetBank>,
 
     pub change: u32,
     last_runtime_change: u32
@@ -25,13 +36,21 @@ impl InterfaceData {
         Self {
             mode: InterfaceMode::Draft,
             workspace: None,
+            workspace_watch: false,
             draft_content: library::MODULE_EXAMPLES[0].0.to_module_content(),
+            library_path: library::default_library_path(),
+            user_library: None,
 
             runtime_target_state: RuntimeState::Offline,
             runtime_clip: true,
             runtime_input_noise: false,
+            runtime_watchdog_budget: watchdog::DEFAULT_BUDGET,
+            runtime_memory_limit: module::DEFAULT_MEMORY_LIMIT,
+            runtime_crossfade_ms: crossfade::DEFAULT_CROSSFADE_MS,
+            runtime_triggers: 0,
 
             parameters: BTreeMap::new(),
+            draft_presets: BTreeMap::new(),
 
             change: 0,
             last_runtime_change: 0
@@ -62,6 +81,27 @@ impl InterfaceData {
         self.mark_changed();
     }
 
+    pub fn set_runtime_watchdog_budget(&mut self, runtime_watchdog_budget: f32) {
+        self.runtime_watchdog_budget = runtime_watchdog_budget;
+        self.mark_changed();
+    }
+
+    pub fn set_runtime_memory_limit(&mut self, runtime_memory_limit: usize) {
+        self.runtime_memory_limit = runtime_memory_limit;
+        self.mark_changed();
+    }
+
+    pub fn set_runtime_crossfade_ms(&mut self, runtime_crossfade_ms: f32) {
+        self.runtime_crossfade_ms = runtime_crossfade_ms;
+        self.mark_changed();
+    }
+
+    // Triggers are counted, so the runtime can tell a new press from one it has already handled.
+    pub fn trigger_runtime(&mut self) {
+        self.runtime_triggers = self.runtime_triggers.wrapping_add(1);
+        self.mark_changed();
+    }
+
     pub fn mark_changed(&mut self) {
         self.change = self.change + 1;
     }
@@ -1,26 +1,45 @@
-use super::{interface_module::InterfaceModule, DEFAULT_SPACE};
+use super::{interface_module::InterfaceModule, InterfaceMode, DEFAULT_SPACE};
 
 use nih_plug_egui::egui::{self, Ui} ;
-use crate::{ runtime::{ parameter::Parameter, runtime_data::RuntimeData }, InterfaceData };
+use crate::{ runtime::{ parameter::Parameter, preset::{ Preset, PresetBank }, runtime_data::RuntimeData }, InterfaceData };
 
 const PARAMETER_GRID_ID: &str = "Central/Parameters";
+const PRESET_GRID_ID: &str = "Central/Presets";
+const MORPH_FROM_ID: &str = "Central/Presets/MorphFrom";
+const MORPH_TO_ID: &str = "Central/Presets/MorphTo";
 
 pub struct InterfaceRuntime {
     pub module: Option<InterfaceModule>,
-    pub view: InterfaceRuntimeView
+    pub view: InterfaceRuntimeView,
+
+    preset_name: String,
+    preset_status: String,
+    // Presets of the open workspace, and the path they were read from.
+    workspace_presets: Option<(String, PresetBank)>,
+    morph_from: Option<String>,
+    morph_to: Option<String>,
+    morph: f32
 }
 
 #[derive(PartialEq)]
 pub enum InterfaceRuntimeView {
     Interface,
-    Parameters
+    Parameters,
+    Presets
 }
 
 impl InterfaceRuntime {
     pub fn new() -> InterfaceRuntime {
         Self {
             module: None,
-            view: InterfaceRuntimeView::Interface
+            view: InterfaceRuntimeView::Interface,
+
+            preset_name: String::new(),
+            preset_status: String::new(),
+            workspace_presets: None,
+            morph_from: None,
+            morph_to: None,
+            morph: 0.0
         }
     }
 
@@ -32,6 +51,148 @@ impl InterfaceRuntime {
             },
             InterfaceRuntimeView::Parameters => {
                 self.draw_parameters(ui, runtime_data, interface_data);
+            },
+            InterfaceRuntimeView::Presets => {
+                self.draw_presets(ui, runtime_data, interface_data);
+            }
+        }
+    }
+
+    pub fn draw_presets(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
+        let mut bank = self.get_preset_bank(runtime_data, interface_data);
+        let mut bank_changed = false;
+        let mut parameters_changed = false;
+
+        let location = match interface_data.mode {
+            InterfaceMode::Workspace => "the workspace folder",
+            InterfaceMode::Draft => "the plugin state"
+        };
+        ui.label(format!("Presets for \"{name}\" are kept in {location}.", name = runtime_data.module_name, location = location));
+        ui.separator();
+
+        ui.add_enabled_ui(!interface_data.parameters.is_empty(), |ui| {
+            ui.horizontal(|ui| {
+                ui.text_edit_singleline(&mut self.preset_name);
+
+                if ui.add_enabled(!self.preset_name.trim().is_empty(), egui::Button::new("Save")).clicked() {
+                    bank.save(Preset::from_parameters(self.preset_name.trim().to_string(), &interface_data.parameters));
+                    bank_changed = true;
+                }
+            });
+
+            ui.add_space(DEFAULT_SPACE);
+
+            egui::Grid::new(PRESET_GRID_ID)
+                .num_columns(3)
+                .spacing([DEFAULT_SPACE * 4.0, DEFAULT_SPACE])
+                .show(ui, |ui| {
+                let mut removed = None;
+                for preset in &bank.presets {
+                    ui.label(&preset.name);
+
+                    if ui.button("Recall").clicked() {
+                        preset.apply(&mut interface_data.parameters);
+                        self.preset_name = preset.name.clone();
+                        parameters_changed = true;
+                    }
+
+                    if ui.button("Delete").clicked() {
+                        removed = Some(preset.name.clone());
+                    }
+                    ui.end_row();
+                }
+
+                match removed {
+                    Some(name) => {
+                        bank.remove(&name);
+                        bank_changed = true;
+                    },
+                    None => ()
+                }
+            });
+
+            ui.separator();
+
+            ui.horizontal(|ui| {
+                ui.label("Morph");
+                InterfaceRuntime::draw_preset_selection(ui, MORPH_FROM_ID, &bank, &mut self.morph_from);
+                let slider = ui.add(egui::Slider::new(&mut self.morph, 0.0..=1.0).show_value(false));
+                InterfaceRuntime::draw_preset_selection(ui, MORPH_TO_ID, &bank, &mut self.morph_to);
+
+                let from = self.morph_from.as_deref().and_then(|n| bank.get(n));
+                let to = self.morph_to.as_deref().and_then(|n| bank.get(n));
+                match (from, to) {
+                    (Some(from), Some(to)) => {
+                        if slider.changed() {
+                            Preset::morph(from, to, self.morph, &mut interface_data.parameters);
+                            parameters_changed = true;
+                        }
+                    },
+                    _ => ()
+                }
+            });
+        });
+
+        if !self.preset_status.is_empty() {
+            ui.label(&self.preset_status);
+        }
+
+        if bank_changed {
+            self.store_preset_bank(bank, runtime_data, interface_data);
+        }
+
+        if parameters_changed {
+            interface_data.mark_changed();
+        }
+    }
+
+    fn draw_preset_selection(ui: &mut Ui, id: &str, bank: &PresetBank, selection: &mut Option<String>) {
+        egui::ComboBox::from_id_source(id)
+            .selected_text(selection.clone().unwrap_or(String::from("...")))
+            .show_ui(ui, |ui| {
+                for preset in &bank.presets {
+                    ui.selectable_value(selection, Some(preset.name.clone()), &preset.name);
+                }
+            });
+    }
+
+    fn get_preset_bank(&mut self, runtime_data: &RuntimeData, interface_data: &InterfaceData) -> PresetBank {
+        match (&interface_data.mode, &interface_data.workspace) {
+            (InterfaceMode::Workspace, Some(workspace)) => {
+                match &self.workspace_presets {
+                    Some((path, bank)) if *path == workspace.path => return bank.clone(),
+                    _ => ()
+                }
+
+                let bank = match PresetBank::load_from_workspace(&workspace.path) {
+                    Ok(b) => b,
+                    Err(e) => {
+                        self.preset_status = e;
+                        PresetBank::new()
+                    }
+                };
+                self.workspace_presets = Some((workspace.path.clone(), bank.clone()));
+
+                return bank;
+            },
+            _ => {
+                return interface_data.draft_presets.get(&runtime_data.module_name).cloned().unwrap_or(PresetBank::new());
+            }
+        }
+    }
+
+    fn store_preset_bank(&mut self, bank: PresetBank, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
+        match (&interface_data.mode, &interface_data.workspace) {
+            (InterfaceMode::Workspace, Some(workspace)) => {
+                self.preset_status = match bank.save_to_workspace(&workspace.path) {
+                    Ok(()) => String::new(),
+                    Err(e) => e
+                };
+                self.workspace_presets = Some((workspace.path.clone(), bank));
+            },
+            _ => {
+                interface_data.draft_presets.insert(runtime_data.module_name.clone(), bank);
+                interface_data.mark_changed();
             }
         }
     }
@@ -47,7 +208,7 @@ impl InterfaceRuntime {
         egui::ScrollArea::vertical()
             .show(ui, |ui| {
                 egui::Grid::new(PARAMETER_GRID_ID)
-                    .num_columns(2)
+                    .num_columns(3)
                     .spacing([DEFAULT_SPACE * 4.0, DEFAULT_SPACE])
                     .show(ui, |ui| {
                     for parameter in &mut interface_data.parameters {
@@ -68,6 +229,10 @@ impl InterfaceRuntime {
     fn draw_parameter(&mut self, ui: &mut Ui, parameter: &mut Parameter) {
         ui.label(&parameter.name);
         parameter.draw(ui);
+        match parameter.mapped_slot {
+            Some(slot) => ui.label(format!("Macro {}", slot + 1)).on_hover_text("The host can automate this parameter through this macro."),
+            None => ui.label("")
+        };
         ui.end_row();
     }
 }
\ No newline at end of file
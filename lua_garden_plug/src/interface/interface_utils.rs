@@ -1,3 +1,4 @@
+use std::time::SystemTime;
 use nih_plug_egui::egui::{self, RichText, Ui, Vec2, WidgetText};
 
 pub const TOOLTIP_HOVER_WIDTH: f32 = 300.0;
@@ -21,4 +22,19 @@ pub fn toggle_value(ui: &mut Ui, value: &mut bool, true_text: impl Into<WidgetTe
             *value = !*value;
         }
     }
-}
\ No newline at end of file
+}
+
+// How long ago a time was, roughly.
+pub fn format_age(time: SystemTime) -> String {
+    let seconds = match SystemTime::now().duration_since(time) {
+        Ok(d) => d.as_secs(),
+        Err(_e) => 0
+    };
+
+    return match seconds {
+        0..=59 => String::from("just now"),
+        60..=3599 => format!("{} min ago", seconds / 60),
+        3600..=86399 => format!("{} h ago", seconds / 3600),
+        _ => format!("{} days ago", seconds / 86400)
+    };
+}
@@ -4,13 +4,13 @@ pub mod interface_module;
 pub mod interface_runtime;
 pub mod parameter;
 
-use std::{ hash::Hash, sync::{ Arc, RwLock } };
+use std::{ hash::Hash, sync::{ Arc, RwLock }, time::{ Duration, Instant, SystemTime } };
 use interface_runtime::{InterfaceRuntime, InterfaceRuntimeView};
 use mlem_egui_themes::Theme;
 use nih_plug::prelude::*;
 use nih_plug_egui::{ egui::{ self, Context, Ui }, EguiState };
 use interface_data::InterfaceData;
-use crate::{ consts, ConsoleReceiver, runtime::{library, workspace::Workspace}, LuaGardenParams, runtime::runtime_data::RuntimeState, RuntimeData };
+use crate::{ consts, ConsoleReceiver, runtime::{crossfade, exchange::{ self, ModuleExchange, ModuleMetadata }, library, manifest::ModuleManifest, module, sandbox::SandboxLevel, user_library::UserLibrary, utils, watchdog, workspace::Workspace, workspace_browser::{ WorkspaceHistory, WorkspaceListing }}, LuaGardenParams, runtime::runtime_data::RuntimeState, RuntimeData };
 
 const DEFAULT_SPACE: f32 = 4.0;
 const TOP_ID: &str = "Top";
@@ -24,6 +24,15 @@ const CONSOLE_PREVIEW_CHARS: usize = 40;
 const DRAFT_EDITOR_ID: &str = "Central/DraftEditor";
 const BAR_HEIGHT: f32 = 20.0;
 const LOAD_BUTTON_WIDTH: f32 = 64.0;
+const BUDGET_DRAG_SPEED: f32 = 0.01;
+const MEMORY_LIMIT_DRAG_SPEED: f32 = 1.0;
+const CROSSFADE_DRAG_SPEED: f32 = 1.0;
+const BYTES_PER_MB: usize = 1024 * 1024;
+const ERROR_PREVIEW_LINES: usize = 4;
+const RETRY_DELAY: Duration = Duration::from_millis(500);
+const WATCH_POLL_INTERVAL: Duration = Duration::from_millis(250);
+const WORKSPACE_BROWSER_ID: &str = "Central/WorkspaceBrowser";
+const WORKSPACE_BROWSER_HEIGHT: f32 = 240.0;
 
 pub struct Interface {
     pub console: ConsoleReceiver,
@@ -36,9 +45,19 @@ pub struct Interface {
 
     create_workspace_path: String,
     open_workspace_path: String,
+    workspace_root_path: String,
+    workspace_history: WorkspaceHistory,
+    workspace_listings: Vec<WorkspaceListing>,
+    share_string: String,
+    garden_path: String,
 
     interface_runtime: InterfaceRuntime,
 
+    bypass_since: Option<SystemTime>,
+    retry_time: Option<Instant>,
+    watch_since: Option<SystemTime>,
+    last_watch_poll: Instant,
+
     theme: usize,
     themes: [mlem_egui_themes::Theme; 4],
 }
@@ -77,9 +96,19 @@ impl Interface {
 
             create_workspace_path: library::default_workspaces_path(),
             open_workspace_path: library::default_workspaces_path(),
+            workspace_root_path: String::new(),
+            workspace_history: WorkspaceHistory::load(),
+            workspace_listings: Vec::new(),
+            share_string: String::new(),
+            garden_path: format!("{path}/module.{extension}", path = library::default_workspaces_path(), extension = exchange::GARDEN_EXTENSION),
 
             interface_runtime: InterfaceRuntime::new(),
 
+            bypass_since: None,
+            retry_time: None,
+            watch_since: None,
+            last_watch_poll: Instant::now(),
+
             theme: 0,
             themes: [
                 mlem_egui_themes::garden_night(),
@@ -130,11 +159,13 @@ impl Interface {
         self.console.log(format!("{}", consts::MOTD));
     }
     
-    fn draw_interface(&mut self, egui_ctx: &Context, _setter: &ParamSetter, _state: &mut (), _params: Arc<LuaGardenParams>, runtime_data: Arc<RwLock<RuntimeData>>, interface_data: Arc<RwLock<InterfaceData>>) {    
+    fn draw_interface(&mut self, egui_ctx: &Context, setter: &ParamSetter, _state: &mut (), params: Arc<LuaGardenParams>, runtime_data: Arc<RwLock<RuntimeData>>, interface_data: Arc<RwLock<InterfaceData>>) {    
         let runtime_data = runtime_data.read().unwrap().clone();
         let mut interface_data = interface_data.write().unwrap();
         
         interface_data.update_from_runtime(&runtime_data);
+        self.update_retry(&runtime_data, &mut interface_data);
+        self.update_watch(&runtime_data, &mut interface_data);
 
         egui::TopBottomPanel::top(TOP_ID).show(egui_ctx, |ui| {
             ui.horizontal(|ui| {
@@ -143,7 +174,7 @@ impl Interface {
                 ui.separator();
                 self.draw_center_view_selection(ui, &mut interface_data);
                 ui.separator();
-                self.draw_modules_menu(ui, &mut interface_data);
+                self.draw_modules_menu(ui, &runtime_data, &mut interface_data);
     
                 ui.with_layout(egui::Layout::right_to_left(egui::Align::Max), |ui| {
                     self.draw_panic_button(ui, &runtime_data, &mut interface_data);
@@ -155,6 +186,10 @@ impl Interface {
             if interface_data.runtime_target_state == RuntimeState::Offline {
                 self.center_view = CenterView::Code;
             }
+
+            if runtime_data.state == RuntimeState::Bypass {
+                self.draw_error(ui, &runtime_data);
+            }
             
             match self.center_view {
                 CenterView::Code => {
@@ -172,7 +207,7 @@ impl Interface {
                 }
             }
 
-            self.draw_console(ui, &runtime_data, CONSOLE_MAIN_ID);
+            self.draw_console(ui, &runtime_data, &mut interface_data, CONSOLE_MAIN_ID);
         });
 
         egui::TopBottomPanel::bottom(BOTTOM_ID).show(egui_ctx, |ui| {
@@ -186,6 +221,34 @@ impl Interface {
                 });
             });
         });
+
+        self.update_macros(setter, &params, &interface_data);
+    }
+
+    // Parameters changed here or by a preset move their macro along, so the host shows the value and automation doesn't undo it.
+    fn update_macros(&self, setter: &ParamSetter, params: &LuaGardenParams, interface_data: &InterfaceData) {
+        let mapping = match params.macro_mapping.try_read() {
+            Ok(m) => m,
+            Err(_e) => return
+        };
+
+        for parameter in interface_data.parameters.values() {
+            if !parameter.changed { continue; }
+
+            let (slot, macro_slot) = match parameter.mapped_slot.and_then(|slot| mapping.get(slot).map(|s| (slot, s))) {
+                Some(s) => s,
+                None => continue
+            };
+            if macro_slot.name != parameter.name { continue; }
+
+            let macro_param = &params.macros[slot].value;
+            let value = macro_slot.to_normalized(parameter.value);
+            if macro_param.value() == value { continue; }
+
+            setter.begin_set_parameter(macro_param);
+            setter.set_parameter(macro_param, value);
+            setter.end_set_parameter(macro_param);
+        }
     }
     
     fn draw_darkmode_toggle(&mut self, egui_ctx: &Context, ui: &mut Ui) {
@@ -269,10 +332,23 @@ impl Interface {
             if ui.selectable_value(&mut interface_data.mode, InterfaceMode::Workspace, "Workspace").clicked() {
                 ui.close_menu();
             }
+
+            ui.separator();
+
+            ui.menu_button("User library", |ui| {
+                ui.set_max_width(ABOUT_MENU_WIDTH);
+
+                ui.label("Lua files in this folder can be required by any module. Reload to pick up changes.");
+                ui.text_edit_singleline(&mut interface_data.library_path);
+
+                if ui.button("Use default").clicked() {
+                    interface_data.library_path = library::default_library_path();
+                }
+            });
         });
     }
 
-    fn draw_modules_menu(&mut self, ui: &mut Ui, interface_data: &mut InterfaceData) {
+    fn draw_modules_menu(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
         if interface_data.mode == InterfaceMode::Workspace { return; }
 
         ui.menu_button("Modules", |ui| {
@@ -291,11 +367,91 @@ impl Interface {
                     }
                 }
             });
+
+            ui.separator();
+
+            if ui.button("Copy share string").clicked() {
+                match self.draft_exchange(runtime_data, interface_data).to_share_string() {
+                    Ok(share_string) => {
+                        ui.output_mut(|o| o.copied_text = share_string);
+                        self.console.log(format!("Copied share string for module {:x}.", interface_data.draft_content.generate_hash()));
+                    },
+                    Err(e) => self.console.log(format!("Couldn't create share string: {}", e))
+                }
+                ui.close_menu();
+            }
+
+            ui.menu_button("Paste share string", |ui| {
+                ui.set_max_width(ABOUT_MENU_WIDTH);
+
+                ui.label("Paste a share string to replace the draft.");
+                ui.text_edit_singleline(&mut self.share_string);
+
+                if ui.add_enabled(!self.share_string.is_empty(), egui::Button::new("Import")).clicked() {
+                    match ModuleExchange::from_share_string(&self.share_string) {
+                        Ok(exchange) => {
+                            self.import_exchange(exchange, interface_data);
+                            self.share_string.clear();
+                            ui.close_menu();
+                        },
+                        Err(e) => {
+                            self.console.log(format!("Couldn't import share string: {}", e));
+                        }
+                    }
+                }
+            });
+
+            ui.menu_button(format!(".{} file", exchange::GARDEN_EXTENSION), |ui| {
+                ui.set_max_width(ABOUT_MENU_WIDTH);
+
+                ui.label("Save the draft to, or open a draft from a file at");
+                ui.text_edit_singleline(&mut self.garden_path);
+
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        match self.draft_exchange(runtime_data, interface_data).write_to_file(&self.garden_path) {
+                            Ok(()) => self.console.log(format!("Saved module to {}.", self.garden_path)),
+                            Err(e) => self.console.log(format!("Couldn't save module: {}", e))
+                        }
+                        ui.close_menu();
+                    }
+
+                    if ui.button("Open").clicked() {
+                        match ModuleExchange::read_from_file(&self.garden_path) {
+                            Ok(exchange) => {
+                                self.import_exchange(exchange, interface_data);
+                                ui.close_menu();
+                            },
+                            Err(e) => self.console.log(format!("Couldn't open module: {}", e))
+                        }
+                    }
+                });
+            });
         });
     }
 
+    // Metadata comes from the running module, which is usually the draft.
+    fn draft_exchange(&self, runtime_data: &RuntimeData, interface_data: &InterfaceData) -> ModuleExchange {
+        let metadata = if runtime_data.module_name.is_empty() {
+            ModuleMetadata::unknown()
+        } else {
+            ModuleMetadata::new(runtime_data.module_name.clone(), runtime_data.module_author.clone(), runtime_data.module_description.clone())
+        };
+
+        return ModuleExchange::new(interface_data.draft_content.clone(), metadata);
+    }
+
+    fn import_exchange(&mut self, exchange: ModuleExchange, interface_data: &mut InterfaceData) {
+        self.console.log(format!("Imported \"{name}\" by {authors} ({hash:x}, format v{version}).", 
+            name = exchange.metadata.name,
+            authors = exchange.metadata.authors,
+            hash = exchange.content.generate_hash(),
+            version = exchange.version));
+        interface_data.draft_content = exchange.content;
+    }
+
     fn draw_panic_button(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
-        if runtime_data.state == RuntimeState::Online {
+        if runtime_data.state == RuntimeState::Online || runtime_data.state == RuntimeState::Bypass {
             if ui.button("\u{E4E4} PANIC").clicked() {
                 interface_data.set_runtime_target_state(RuntimeState::Clear);
             }
@@ -309,6 +465,18 @@ impl Interface {
     }
 
     fn draw_signal_flow(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
+        let mut watchdog_budget = interface_data.runtime_watchdog_budget;
+        ui.add(egui::DragValue::new(&mut watchdog_budget)
+            .speed(BUDGET_DRAG_SPEED)
+            .clamp_range(watchdog::MIN_BUDGET..=watchdog::MAX_BUDGET)
+            .custom_formatter(|n, _| format!("{:.0}%", n * 100.0))
+            .custom_parser(|s| s.trim_end_matches('%').trim().parse::<f64>().ok().map(|n| n / 100.0)))
+            .on_hover_text("Budget\nThe share of each buffer's time a module may use before it's bypassed.");
+
+        if watchdog_budget != interface_data.runtime_watchdog_budget {
+            interface_data.set_runtime_watchdog_budget(watchdog_budget);
+        }
+
         let mut clip = interface_data.runtime_clip;
         interface_utils::toggle_value(ui, &mut clip, "\u{EA9E} Clip", "\u{EA9A} No clip", [LOAD_BUTTON_WIDTH, ui.available_height()]);
 
@@ -325,6 +493,11 @@ impl Interface {
                     description = &runtime_data.module_description));
                 ui.label("\u{E1D7}");
             }
+            RuntimeState::Bypass => {
+                ui.label("\u{E1D7}");
+                ui.label(format!("{} (bypassed)", &runtime_data.module_name)).on_hover_text("The module failed and is bypassed.\nReload or edit it to try again.");
+                ui.label("\u{E1D7}");
+            }
             _ => {
                 ui.label("\u{E1D7}");
                 ui.label("Module");            
@@ -332,6 +505,13 @@ impl Interface {
             }
         }
 
+        match runtime_data.sandbox {
+            Some(sandbox) => {
+                ui.label(sandbox.name()).on_hover_text(sandbox.description());
+            },
+            None => ()
+        }
+
         let mut input_noise = interface_data.runtime_input_noise;
         interface_utils::toggle_value(ui, &mut input_noise, "\u{E1B4} Noise", "\u{E802} Input", [LOAD_BUTTON_WIDTH, ui.available_height()]);
         interface_data.set_runtime_input_noise(input_noise);
@@ -373,13 +553,17 @@ impl Interface {
             } else {
                 ui.available_height() - BAR_HEIGHT
             };
-            ui.add_sized([ui.available_width(), height], egui::TextEdit::multiline(code.0)
+            let response = ui.add_sized([ui.available_width(), height], egui::TextEdit::multiline(code.0)
                     .font(egui::TextStyle::Monospace)
                     .code_editor()
                     .lock_focus(true)
                     .desired_width(f32::INFINITY)
                     .id(Into::into(format!("{prefix}/{id}", prefix = DRAFT_EDITOR_ID, id = code.1))),
             );
+
+            if response.changed() && runtime_data.state == RuntimeState::Bypass {
+                self.retry_time = Some(Instant::now());
+            }
         });
     }
     
@@ -400,11 +584,7 @@ impl Interface {
                     if ui.button("Create").clicked() {
                         let path = self.create_workspace_path.clone();
                         match Workspace::create_at_path(path, None) {
-                            Ok(w) => {
-                                self.show_create_workspace(false);
-                                self.show_open_workspace(false);
-                                interface_data.workspace = Some(w);
-                            },
+                            Ok(w) => self.use_workspace(w, interface_data),
                             Err(e) => {
                                 self.console.log(format!("Failed to create workspace: {}", e));
                             }
@@ -415,11 +595,7 @@ impl Interface {
                         let path = self.create_workspace_path.clone();
                         let content = interface_data.draft_content.clone();
                         match Workspace::create_at_path(path, Some(content)) {
-                            Ok(w) => {
-                                self.show_create_workspace(false);
-                                self.show_open_workspace(false);
-                                interface_data.workspace = Some(w);
-                            },
+                            Ok(w) => self.use_workspace(w, interface_data),
                             Err(e) => {
                                 self.console.log(format!("Failed to create workspace: {}", e));
                             }
@@ -432,17 +608,8 @@ impl Interface {
                     }
 
                     if ui.button("Open").clicked() {
-                        let path = self.create_workspace_path.clone();
-                        match Workspace::load_from_path(path) {
-                            Ok(w) => {
-                                self.show_create_workspace(false);
-                                self.show_open_workspace(false);
-                                interface_data.workspace = Some(w);
-                            },
-                            Err(e) => {
-                                self.console.log(format!("Failed to open workspace: {}", e));
-                            }
-                        }
+                        let path = self.open_workspace_path.clone();
+                        self.open_workspace(path, interface_data);
                     }
                 } else {
                     if ui.button("Create").clicked() {
@@ -476,14 +643,50 @@ impl Interface {
                 ui.text_edit_singleline(&mut self.open_workspace_path);
                 ui.label(".");
             });
+
+            ui.add_space(DEFAULT_SPACE * 2.0);
+            self.draw_workspace_browser(ui, interface_data);
         } else {
-            match &interface_data.workspace {
+            match &mut interface_data.workspace {
                 Some(workspace) => {
                     ui.horizontal(|ui| {
                         ui.label("Workspace loaded at");
                         ui.monospace(format!("{}", workspace.path));
                         ui.label(".");
                     });
+
+                    match &workspace.manifest {
+                        Some(manifest) => {
+                            ui.horizontal(|ui| {
+                                ui.label(format!("{name} v{version}", name = workspace.name(), version = manifest.version));
+                                if !manifest.authors.is_empty() {
+                                    ui.label(format!("by {}", manifest.authors.join(", ")));
+                                }
+                                if !manifest.tags.is_empty() {
+                                    ui.monospace(manifest.tags.join(" "));
+                                }
+                            }).response.on_hover_text(manifest.description.clone());
+                        },
+                        None => ()
+                    }
+
+                    let mut trusted = workspace.sandbox == SandboxLevel::Trusted;
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut trusted, "Trusted");
+                        interface_utils::help_label(ui, format!("Trusted workspaces can access files and run programs. Only trust code you know.\n\
+                            Changes apply when the module is reloaded."));
+
+                        if !trusted && workspace.suggested_sandbox() == Some(SandboxLevel::Trusted) {
+                            ui.colored_label(ui.visuals().warn_fg_color, format!("{} asks to be trusted.", library::MANIFEST_PATH));
+                        }
+                    });
+                    workspace.sandbox = if trusted { SandboxLevel::Trusted } else { SandboxLevel::Sandboxed };
+
+                    ui.horizontal(|ui| {
+                        ui.checkbox(&mut interface_data.workspace_watch, "Watch while the editor is open");
+                        interface_utils::help_label(ui, format!("Reloads the module when workspace files change on disk. \
+                            Files are only checked while the {name} editor window is open.", name = consts::NAME));
+                    });
                 },
                 None => {
                     ui.label("No workspace loaded. Create or open one.");
@@ -494,13 +697,104 @@ impl Interface {
         ui.add_space(DEFAULT_SPACE * 4.0);
     }
     
+    // Recent workspaces, and the workspaces found in the browser's folders. A click opens one.
+    fn draw_workspace_browser(&mut self, ui: &mut Ui, interface_data: &mut InterfaceData) {
+        let mut open_path = None;
+        let mut remove_root = None;
+
+        egui::ScrollArea::vertical()
+            .id_source(WORKSPACE_BROWSER_ID)
+            .max_height(WORKSPACE_BROWSER_HEIGHT)
+            .show(ui, |ui| {
+                if !self.workspace_history.recent.is_empty() {
+                    ui.label("Recent");
+                    for path in &self.workspace_history.recent {
+                        let name = match self.workspace_listings.iter().find(|l| &l.path == path) {
+                            Some(l) => l.name.clone(),
+                            None => ModuleManifest::for_folder(path).name
+                        };
+
+                        if ui.button(name).on_hover_text(path.as_str()).clicked() {
+                            open_path = Some(path.clone());
+                        }
+                    }
+
+                    ui.add_space(DEFAULT_SPACE * 2.0);
+                }
+
+                ui.label("Workspaces");
+                if self.workspace_listings.is_empty() {
+                    ui.label("None found. Add folders to look in below.");
+                }
+
+                for listing in &self.workspace_listings {
+                    ui.horizontal(|ui| {
+                        if ui.button(listing.name.as_str()).on_hover_text(listing.path.as_str()).clicked() {
+                            open_path = Some(listing.path.clone());
+                        }
+
+                        match listing.modified {
+                            Some(m) => { ui.monospace(interface_utils::format_age(m)); },
+                            None => ()
+                        }
+                        ui.label(listing.description.as_str());
+                    });
+                }
+            });
+
+        ui.add_space(DEFAULT_SPACE * 2.0);
+
+        ui.horizontal(|ui| {
+            ui.label("Look in");
+            ui.monospace(library::default_workspaces_path());
+
+            for root in &self.workspace_history.roots {
+                ui.monospace(root.as_str());
+                if ui.small_button("Remove").on_hover_text("Stop looking in this folder").clicked() {
+                    remove_root = Some(root.clone());
+                }
+            }
+        });
+
+        ui.horizontal(|ui| {
+            ui.text_edit_singleline(&mut self.workspace_root_path);
+
+            if ui.add_enabled(!self.workspace_root_path.is_empty(), egui::Button::new("Add folder")).clicked() {
+                let root = self.workspace_root_path.clone();
+                self.workspace_history.add_root(&root);
+                self.workspace_root_path.clear();
+                self.save_workspace_history();
+                self.workspace_listings = self.workspace_history.scan();
+            }
+
+            if ui.button("Rescan").clicked() {
+                self.workspace_listings = self.workspace_history.scan();
+            }
+        });
+
+        match remove_root {
+            Some(root) => {
+                self.workspace_history.remove_root(&root);
+                self.save_workspace_history();
+                self.workspace_listings = self.workspace_history.scan();
+            },
+            None => ()
+        }
+
+        match open_path {
+            Some(path) => self.open_workspace(path, interface_data),
+            None => ()
+        }
+    }
+
     fn draw_module_interface(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
         ui.horizontal(|ui| {
             ui.selectable_value(&mut self.interface_runtime.view, InterfaceRuntimeView::Interface, "Interface");
             ui.separator();
 
             ui.horizontal(|ui| {
-                ui.selectable_value(&mut self.interface_runtime.view, InterfaceRuntimeView::Parameters, "Parameters")
+                ui.selectable_value(&mut self.interface_runtime.view, InterfaceRuntimeView::Parameters, "Parameters");
+                ui.selectable_value(&mut self.interface_runtime.view, InterfaceRuntimeView::Presets, "Presets")
             });
 
             ui.with_layout(egui::Layout::right_to_left(egui::Align::Max), |ui| {
@@ -525,7 +819,7 @@ impl Interface {
                             interface_data.set_runtime_target_state(RuntimeState::Refresh);
                         }
                     },
-                    RuntimeState::Online => {
+                    RuntimeState::Online | RuntimeState::Bypass => {
                         if ui.add_sized([LOAD_BUTTON_WIDTH, ui.available_height()], egui::Button::new("\u{E522} Reload")).clicked() {
                             self.update_workspace(interface_data);
                             interface_data.set_runtime_target_state(RuntimeState::Refresh);
@@ -537,11 +831,42 @@ impl Interface {
                         ui.set_enabled(true);
                     }
                 }
+
+                let trigger_button = ui.add_enabled(runtime_data.state == RuntimeState::Online, egui::Button::new("Trigger"))
+                    .on_hover_text("Trigger\nRuns the module's trigger section. MIDI notes and the Trigger parameter do the same.");
+                if trigger_button.clicked() {
+                    interface_data.trigger_runtime();
+                }
+
+                let mut crossfade_ms = interface_data.runtime_crossfade_ms;
+                ui.add(egui::DragValue::new(&mut crossfade_ms)
+                    .speed(CROSSFADE_DRAG_SPEED)
+                    .clamp_range(crossfade::MIN_CROSSFADE_MS..=crossfade::MAX_CROSSFADE_MS)
+                    .max_decimals(0)
+                    .suffix("ms"))
+                    .on_hover_text("Crossfade\nHow long the previous module fades out after a reload.");
+
+                if crossfade_ms != interface_data.runtime_crossfade_ms {
+                    interface_data.set_runtime_crossfade_ms(crossfade_ms);
+                }
             });
         });
     }
     
-    fn draw_console(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, hash: impl Hash) {
+    fn draw_memory_limit(&mut self, ui: &mut Ui, interface_data: &mut InterfaceData) {
+        let mut memory_limit_mb = interface_data.runtime_memory_limit / BYTES_PER_MB;
+        ui.add(egui::DragValue::new(&mut memory_limit_mb)
+            .speed(MEMORY_LIMIT_DRAG_SPEED)
+            .clamp_range((module::MIN_MEMORY_LIMIT / BYTES_PER_MB)..=(module::MAX_MEMORY_LIMIT / BYTES_PER_MB))
+            .suffix("MB"))
+            .on_hover_text("Memory limit\nModules that allocate more than this are taken offline.");
+
+        if memory_limit_mb * BYTES_PER_MB != interface_data.runtime_memory_limit {
+            interface_data.set_runtime_memory_limit(memory_limit_mb * BYTES_PER_MB);
+        }
+    }
+
+    fn draw_console(&mut self, ui: &mut Ui, runtime_data: &RuntimeData, interface_data: &mut InterfaceData, hash: impl Hash) {
         if !self.show_console { return; }
         
         ui.add_space(ui.available_height() - CONSOLE_HEIGHT);
@@ -559,12 +884,26 @@ impl Interface {
                 ui.monospace(":");
         
                 let load = (runtime_data.run_ms / (runtime_data.buffer_size as f32 / runtime_data.sample_rate * 1000.0) * 100.0).floor();
-                let status = format!("({ms:.2}ms / {load:>3}%) at {rate}hz, {buff} samples, {channels} channels.", 
+                let status = format!("({ms:.2}ms / {load:>3}%) at {rate}hz, {buff} samples, {channels} channels ({layout}).", 
                     ms = runtime_data.run_ms,
                     load = load, 
                     rate = runtime_data.sample_rate,
                     buff = runtime_data.buffer_size,
-                    channels = runtime_data.channels);
+                    channels = runtime_data.channels,
+                    layout = runtime_data.layout);
+        
+                let memory = format!("{used:.1}MB / {limit:.0}MB, {drops} drops", 
+                    used = utils::bytes_to_mb(runtime_data.memory_used),
+                    limit = utils::bytes_to_mb(runtime_data.memory_limit),
+                    drops = runtime_data.memory_drops);
+                let memory_details = format!("Lua memory\nUsed: {used:.2}MB\nPeak: {peak:.2}MB\nLimit: {limit:.2}MB\n\
+                    Memory went down {drops} times between runs, {freed:.2}MB freed in total.\nGarbage collector {gc_state}.", 
+                    used = utils::bytes_to_mb(runtime_data.memory_used),
+                    peak = utils::bytes_to_mb(runtime_data.memory_peak),
+                    limit = utils::bytes_to_mb(runtime_data.memory_limit),
+                    drops = runtime_data.memory_drops,
+                    freed = utils::bytes_to_mb(runtime_data.gc_freed),
+                    gc_state = if runtime_data.gc_running { "running" } else { "stopped" });
         
                 match runtime_data.state {
                     RuntimeState::Offline => {
@@ -572,11 +911,19 @@ impl Interface {
                     },
                     RuntimeState::Online => {
                         ui.monospace(format!("Running {}", status));
+                        ui.monospace(memory).on_hover_text(memory_details);
+                    },
+                    RuntimeState::Bypass => {
+                        ui.monospace("Bypassed. Passing dry audio through.");
                     },
                     _ => {
                         ui.monospace("...");
                     }
                 }
+
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Max), |ui| {
+                    self.draw_memory_limit(ui, interface_data);
+                });
             });
         
             ui.with_layout(egui::Layout::top_down_justified(egui::Align::LEFT).with_cross_justify(true), |ui| {
@@ -591,6 +938,25 @@ impl Interface {
         });
     }
 
+    fn draw_error(&mut self, ui: &mut Ui, runtime_data: &RuntimeData) {
+        let error = match &runtime_data.error {
+            Some(e) => e,
+            None => return
+        };
+        let preview: Vec<&str> = error.lines().take(ERROR_PREVIEW_LINES).collect();
+
+        egui::Frame::group(ui.style()).show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.colored_label(ui.visuals().error_fg_color, "\u{E4E0} Module bypassed. Reload or edit the module to try again.");
+            ui.monospace(preview.join("\n")).on_hover_ui(|ui| {
+                ui.set_max_width(interface_utils::TOOLTIP_HOVER_WIDTH * 2.0);
+                ui.monospace(error);
+            });
+        });
+
+        ui.add_space(DEFAULT_SPACE);
+    }
+
     fn draw_info(&mut self, ui: &mut Ui) {
         ui.vertical(|ui| {
             ui.heading(format!("{icon} {name}", icon = consts::ICON, name = consts::NAME));
@@ -608,13 +974,116 @@ impl Interface {
 
     fn show_open_workspace(&mut self, show: bool) {
         self.show_open_workspace = show;
+
+        if show {
+            self.workspace_listings = self.workspace_history.scan();
+        }
+    }
+
+    fn open_workspace(&mut self, path: String, interface_data: &mut InterfaceData) {
+        match Workspace::load_from_path(path) {
+            Ok(w) => self.use_workspace(w, interface_data),
+            Err(e) => {
+                self.console.log(format!("Failed to open workspace: {}", e));
+            }
+        }
+    }
+
+    fn use_workspace(&mut self, workspace: Workspace, interface_data: &mut InterfaceData) {
+        self.show_create_workspace(false);
+        self.show_open_workspace(false);
+
+        self.workspace_history.add_recent(&workspace.path);
+        self.save_workspace_history();
+        self.console.log(format!("Opened workspace {name} ({hash:x}).", name = workspace.name(), hash = workspace.generate_hash()));
+        interface_data.workspace = Some(workspace);
+    }
+
+    fn save_workspace_history(&mut self) {
+        match self.workspace_history.save() {
+            Ok(()) => (),
+            Err(e) => self.console.log(e)
+        }
     }
 
     fn get_theme(&self) -> Theme {
         return self.themes[self.theme];
     }
 
+    // Retries a bypassed module shortly after its code was edited, in the draft editor or on disk.
+    fn update_retry(&mut self, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
+        if runtime_data.state != RuntimeState::Bypass {
+            self.bypass_since = None;
+            self.retry_time = None;
+            return;
+        }
+
+        let bypass_since = *self.bypass_since.get_or_insert_with(SystemTime::now);
+
+        // Watching reloads on its own
+        if interface_data.mode == InterfaceMode::Workspace && !interface_data.workspace_watch && self.retry_time == None {
+            match &interface_data.workspace {
+                Some(workspace) => {
+                    if workspace.modified_since(bypass_since) {
+                        self.retry_time = Some(Instant::now());
+                    }
+                },
+                None => ()
+            }
+        }
+
+        match self.retry_time {
+            Some(time) => {
+                if time.elapsed() < RETRY_DELAY { return; }
+
+                self.console.log(format!("Module edited. Retrying..."));
+                self.retry_time = None;
+                self.bypass_since = Some(SystemTime::now());
+                self.update_workspace(interface_data);
+                interface_data.set_runtime_target_state(RuntimeState::Refresh);
+            },
+            None => ()
+        }
+    }
+
+    // Polls the workspace files while watching, and reloads the module when they change. Runs with the editor, so only while it's open.
+    fn update_watch(&mut self, runtime_data: &RuntimeData, interface_data: &mut InterfaceData) {
+        let watching = interface_data.mode == InterfaceMode::Workspace && interface_data.workspace_watch;
+        if !watching {
+            self.watch_since = None;
+            return;
+        }
+
+        if self.last_watch_poll.elapsed() < WATCH_POLL_INTERVAL { return; }
+        self.last_watch_poll = Instant::now();
+
+        let watch_since = *self.watch_since.get_or_insert_with(SystemTime::now);
+        let changed = match &interface_data.workspace {
+            Some(workspace) => workspace.changed_files(watch_since),
+            None => return
+        };
+        if changed.is_empty() { return; }
+
+        // Continue from the latest change, so saves during the reload aren't missed.
+        self.watch_since = changed.iter().map(|c| c.1).max();
+        let files: Vec<&str> = changed.iter().map(|c| c.0.as_str()).collect();
+        self.update_workspace(interface_data);
+
+        match runtime_data.state {
+            RuntimeState::Online | RuntimeState::Bypass => {
+                self.console.log(format!("Workspace changed ({}). Reloading...", files.join(", ")));
+                interface_data.set_runtime_target_state(RuntimeState::Refresh);
+            },
+            _ => {
+                self.console.log(format!("Workspace changed ({}).", files.join(", ")));
+            }
+        }
+    }
+
+    // Rereads the workspace files and rescans the user library before a load, so the audio thread doesn't touch the disk for them.
     fn update_workspace(&mut self, interface_data: &mut InterfaceData) {
+        interface_data.user_library = UserLibrary::scan(&interface_data.library_path);
+
         match &mut interface_data.workspace {
             Some(workspace) => {
                 match workspace.update() {
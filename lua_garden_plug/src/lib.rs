@@ -4,7 +4,7 @@ pub mod interface;
 pub mod console;
 
 use console::ConsoleReceiver;
-use runtime::{ Runtime, runtime_data::RuntimeData, runtime_data::RuntimeState };
+use runtime::{ Runtime, layout::{ self, ChannelLayout }, macros::{ self, MacroMapping }, manifest::EntryFiles, midi::MidiEvent, module_state::ModuleStateField, runtime_data::RuntimeData, runtime_data::RuntimeState, sandbox::SandboxLevel, user_library::UserLibrary, workspace::Workspace };
 use interface::{ interface_data::InterfaceData, Interface };
 use nih_plug::prelude::*;
 use std::sync::{ Arc, RwLock };
@@ -14,32 +14,93 @@ pub struct LuaGarden {
     runtime: Runtime,
     params: Arc<LuaGardenParams>,
     runtime_data: Arc<RwLock<RuntimeData>>,
-    interface_data: Arc<RwLock<InterfaceData>>
+    interface_data: Arc<RwLock<InterfaceData>>,
+
+    // This block's triggers and MIDI events. Preallocated, so collecting them doesn't allocate.
+    triggers: Vec<usize>,
+    midi_events: Vec<MidiEvent>,
+    handled_triggers: u32,
+    trigger_param_held: bool,
+
+    // Macro values last handed to the module, normalized.
+    macro_values: [f32; macros::MACRO_SLOT_COUNT],
+    macro_mapping_change: u32
 }
 
 #[derive(Params)]
 pub struct LuaGardenParams {
     #[persist = "editor-state"]
     editor_state: Arc<EguiState>,
+
+    #[id = "trigger"]
+    pub trigger: BoolParam,
+
+    #[nested(array, group = "Macros")]
+    pub macros: [MacroParams; macros::MACRO_SLOT_COUNT],
+
+    pub macro_mapping: Arc<RwLock<MacroMapping>>,
+
+    #[persist = "module-state"]
+    pub module_state: ModuleStateField,
+}
+
+// A generic slot the host can automate. The runtime maps it onto one of the module's parameters.
+#[derive(Params)]
+pub struct MacroParams {
+    #[id = "macro"]
+    pub value: FloatParam,
 }
 
 impl Default for LuaGarden {
     fn default() -> Self {
         let runtime = Runtime::new(None);
+        let runtime_data = Arc::from(RwLock::new(RuntimeData::new()));
+        let interface_data = Arc::from(RwLock::new(InterfaceData::new()));
 
         Self {
             runtime: runtime,
-            params: Arc::new(LuaGardenParams::default()),
-            runtime_data: Arc::from(RwLock::new(RuntimeData::new())),
-            interface_data: Arc::from(RwLock::new(InterfaceData::new()))
+            params: Arc::new(LuaGardenParams::new(runtime_data.clone(), interface_data.clone())),
+            runtime_data: runtime_data,
+            interface_data: interface_data,
+
+            triggers: Vec::with_capacity(consts::MAX_TRIGGERS_PER_BLOCK),
+            midi_events: Vec::with_capacity(consts::MAX_MIDI_EVENTS_PER_BLOCK),
+            handled_triggers: 0,
+            trigger_param_held: false,
+
+            macro_values: [0.0; macros::MACRO_SLOT_COUNT],
+            macro_mapping_change: 0
         }
     }
 }
 
-impl Default for LuaGardenParams {
-    fn default() -> Self {
+impl LuaGardenParams {
+    fn new(runtime_data: Arc<RwLock<RuntimeData>>, interface_data: Arc<RwLock<InterfaceData>>) -> Self {
+        let macro_mapping = Arc::new(RwLock::new(MacroMapping::new()));
+
         Self {
-            editor_state: EguiState::from_size(consts::WINDOW_SIZE_WIDTH, consts::WINDOW_SIZE_HEIGHT)
+            editor_state: EguiState::from_size(consts::WINDOW_SIZE_WIDTH, consts::WINDOW_SIZE_HEIGHT),
+
+            trigger: BoolParam::new("Trigger", false),
+
+            macros: std::array::from_fn(|slot| MacroParams::new(slot, macro_mapping.clone())),
+            macro_mapping: macro_mapping,
+
+            module_state: ModuleStateField::new(runtime_data, interface_data)
+        }
+    }
+}
+
+impl MacroParams {
+    fn new(slot: usize, macro_mapping: Arc<RwLock<MacroMapping>>) -> Self {
+        Self {
+            value: FloatParam::new(format!("Macro {}", slot + 1), 0.0, FloatRange::Linear { min: 0.0, max: 1.0 })
+                .with_value_to_string(Arc::new(move |value| {
+                    match macro_mapping.try_read() {
+                        Ok(mapping) => mapping.format(slot, value),
+                        Err(_e) => format!("{:.2}", value)
+                    }
+                }))
         }
     }
 }
@@ -49,30 +110,198 @@ impl LuaGarden {
         runtime_data.sample_rate = self.runtime.get_sample_rate();
         runtime_data.buffer_size = self.runtime.get_buffer_size();
         runtime_data.channels = self.runtime.get_channels();
+        runtime_data.layout = self.runtime.get_layout().name;
         runtime_data.run_ms = self.runtime.get_run_ms();
+        runtime_data.memory_used = self.runtime.get_memory_used();
+        runtime_data.memory_peak = self.runtime.get_memory_peak();
+        (runtime_data.memory_drops, runtime_data.gc_freed, runtime_data.gc_running) = self.runtime.get_gc_stats();
+        runtime_data.sandbox = self.runtime.get_sandbox();
+        if runtime_data.error.as_deref() != self.runtime.get_last_error() {
+            runtime_data.error = self.runtime.get_last_error().map(String::from);
+        }
     }
 
-    fn refresh_runtime_module(&mut self, interface_data: &InterfaceData) {
+    fn refresh_runtime_module(&mut self, interface_data: &InterfaceData) -> bool {
+        self.runtime.set_user_library(interface_data.user_library.clone());
+
         match interface_data.mode.clone() {
             interface::InterfaceMode::Draft => {
                 let content = interface_data.draft_content.clone();
-                self.runtime.load_new_module(content);
+                return self.runtime.load_new_module(content, SandboxLevel::Sandboxed, None, None, &EntryFiles::default());
             },
             interface::InterfaceMode::Workspace => {
                 match &interface_data.workspace {
                     Some(w) => {
                         let content = w.content.clone();
-                        self.runtime.load_new_module(content);
+                        return self.runtime.load_new_module(content, w.sandbox, Some(&w.path), Some(&w.files), &w.entry_files());
+                    },
+                    None => return false
+                }
+            }
+        }
+    }
+
+    // Gathers MIDI events, and triggers from the interface, the trigger parameter and MIDI notes.
+    fn collect_events(&mut self, runtime_data: &RuntimeData, context: &mut impl ProcessContext<Self>) {
+        self.triggers.clear();
+        self.midi_events.clear();
+
+        if runtime_data.triggers != self.handled_triggers {
+            self.handled_triggers = runtime_data.triggers;
+            self.triggers.push(0);
+        }
+
+        // Sample accurate automation splits the block at parameter changes, so the edge is always at its start.
+        let trigger_param = self.params.trigger.value();
+        if trigger_param && !self.trigger_param_held {
+            self.triggers.push(0);
+        }
+        self.trigger_param_held = trigger_param;
+
+        while let Some(event) = context.next_event() {
+            match event {
+                NoteEvent::NoteOn { timing, .. } => {
+                    if self.triggers.len() < consts::MAX_TRIGGERS_PER_BLOCK {
+                        self.triggers.push(timing as usize);
+                    }
+                },
+                _ => ()
+            }
+
+            match MidiEvent::from_note_event(&event) {
+                Some(midi_event) => {
+                    if self.midi_events.len() < consts::MAX_MIDI_EVENTS_PER_BLOCK {
+                        self.midi_events.push(midi_event);
+                    }
+                },
+                None => ()
+            }
+        }
+    }
+
+    // Checks the macro slots whenever the runtime data changed. They're only remapped when the module's parameters changed.
+    fn update_macro_mapping(&mut self, runtime_data: &mut RuntimeData) {
+        if self.macro_mapping_change == runtime_data.change { return; }
+
+        let remapped = match self.params.macro_mapping.try_write() {
+            Ok(mut mapping) => mapping.update(&mut runtime_data.parameters),
+            // Try again next block
+            Err(_e) => return
+        };
+
+        // Only movement after the remap reaches the module, so loading doesn't overwrite its defaults.
+        if remapped {
+            for slot in 0..macros::MACRO_SLOT_COUNT {
+                self.macro_values[slot] = self.params.macros[slot].value.value();
+            }
+        }
+
+        self.macro_mapping_change = runtime_data.change;
+    }
+
+    // Sample accurate automation splits the block at parameter changes, so applying them at its start is exact.
+    fn apply_macros(&mut self) {
+        let mapping = match self.params.macro_mapping.try_read() {
+            Ok(m) => m,
+            Err(_e) => return
+        };
+
+        for slot in 0..macros::MACRO_SLOT_COUNT {
+            let value = self.params.macros[slot].value.value();
+            if value == self.macro_values[slot] { continue; }
+            self.macro_values[slot] = value;
+
+            match mapping.get(slot) {
+                Some(s) => self.runtime.set_parameter_value(&s.name, s.to_value(value)),
+                None => ()
+            }
+        }
+    }
+
+    // Brings back the module the host loaded, once.
+    fn restore_module_state(&mut self) {
+        let state = match self.params.module_state.take_pending() {
+            Some(s) => s,
+            None => return
+        };
+
+        let content = match &state.content {
+            Some(c) => c.clone(),
+            None => return
+        };
+
+        // Same lock order as process and the interface, runtime data first.
+        let runtime_data_lock = self.runtime_data.clone();
+        let mut runtime_data = runtime_data_lock.write().unwrap();
+        let interface_data_lock = self.interface_data.clone();
+        let mut interface_data = interface_data_lock.write().unwrap();
+
+        interface_data.runtime_clip = state.clip;
+        interface_data.runtime_input_noise = state.input_noise;
+        interface_data.mode = interface::InterfaceMode::Draft;
+        interface_data.draft_content = content.clone();
+        interface_data.draft_presets = state.draft_presets.clone();
+        match &state.library_path {
+            Some(path) => interface_data.library_path = path.clone(),
+            None => ()
+        }
+        // Not on the audio thread yet, so the library can be scanned here.
+        interface_data.user_library = UserLibrary::scan(&interface_data.library_path);
+
+        match &state.workspace_path {
+            Some(path) => {
+                match Workspace::load_from_path(path.clone()) {
+                    // Trust isn't restored, a project file shouldn't be able to grant it. The user has to again.
+                    Ok(w) => {
+                        if state.sandbox == SandboxLevel::Trusted {
+                            self.runtime.log(format!("The workspace was trusted when saved. Restoring it sandboxed, trust it again to give it full access."));
+                        }
+                        interface_data.workspace = Some(w);
+                        interface_data.mode = interface::InterfaceMode::Workspace;
                     },
-                    None => ()
+                    Err(e) => self.runtime.log(format!("{e}\nRestoring the saved copy as a draft."))
                 }
+            },
+            None => ()
+        }
+
+        if state.online {
+            let load_success = self.refresh_runtime_module(&interface_data);
+            let init_success = load_success && self.runtime.init(None);
+            let reset_success = init_success && self.runtime.reset();
+
+            if reset_success {
+                for parameter in &state.parameters {
+                    self.runtime.set_parameter_value(parameter.0, *parameter.1);
+                }
+                runtime_data.set_state(RuntimeState::Online);
+            } else {
+                runtime_data.set_state(self.failed_runtime_state());
             }
         }
+
+        interface_data.runtime_target_state = runtime_data.state.clone();
+        interface_data.mark_changed();
+    }
+
+    fn trigger_runtime(&mut self) -> bool {
+        for t in 0..self.triggers.len() {
+            if !self.runtime.trigger(self.triggers[t]) {
+                return false;
+            }
+        }
+
+        return true;
     }
 
     fn clear_runtime_module(&mut self){
         self.runtime.load_module(None);
     }
+
+    // Failing modules are kept loaded but bypassed. Running out of memory or having no module takes the runtime offline.
+    fn failed_runtime_state(&self) -> RuntimeState {
+        return self.runtime.failed_state();
+    }
 }
 
 impl Plugin for LuaGarden {
@@ -82,18 +311,104 @@ impl Plugin for LuaGarden {
     const EMAIL: &'static str = "stupidplusplus@gmail.com";
     const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[AudioIOLayout {
-        main_input_channels: NonZeroU32::new(2),
-        main_output_channels: NonZeroU32::new(2),
+    // The first layout is the default. Every layout gets a stereo sidechain.
+    const AUDIO_IO_LAYOUTS: &'static [AudioIOLayout] = &[
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(2),
+            main_output_channels: NonZeroU32::new(2),
 
-        aux_input_ports: &[],
-        aux_output_ports: &[],
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
 
-        names: PortNames::const_default(),
-    }];
+            names: PortNames {
+                layout: Some(layout::STEREO_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(1),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::MONO_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(1),
+            main_output_channels: NonZeroU32::new(2),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::MONO_TO_STEREO_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(4),
+            main_output_channels: NonZeroU32::new(4),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::QUAD_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(6),
+            main_output_channels: NonZeroU32::new(6),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::SURROUND_5_1_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        AudioIOLayout {
+            main_input_channels: NonZeroU32::new(8),
+            main_output_channels: NonZeroU32::new(8),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::SURROUND_7_1_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        },
+        // No main input, for modules that make their own sound from MIDI.
+        AudioIOLayout {
+            main_input_channels: None,
+            main_output_channels: NonZeroU32::new(2),
+
+            aux_input_ports: &[new_nonzero_u32(2)],
+            aux_output_ports: &[],
+
+            names: PortNames {
+                layout: Some(layout::INSTRUMENT_NAME),
+                aux_inputs: &["Sidechain"],
+                ..PortNames::const_default()
+            },
+        }
+    ];
 
-    const MIDI_INPUT: MidiConfig = MidiConfig::None;
-    const MIDI_OUTPUT: MidiConfig = MidiConfig::None;
+    const MIDI_INPUT: MidiConfig = MidiConfig::MidiCCs;
+    const MIDI_OUTPUT: MidiConfig = MidiConfig::MidiCCs;
 
     const SAMPLE_ACCURATE_AUTOMATION: bool = true;
 
@@ -119,11 +434,13 @@ impl Plugin for LuaGarden {
 
     fn initialize(
         &mut self,
-        _audio_io_layout: &AudioIOLayout,
+        audio_io_layout: &AudioIOLayout,
         _buffer_config: &BufferConfig,
         _context: &mut impl InitContext<Self>,
     ) -> bool {
+        self.runtime.set_layout(ChannelLayout::from_audio_io_layout(audio_io_layout));
         let _ = self.runtime.init(Some(_buffer_config.sample_rate));
+        self.restore_module_state();
 
         return true;
     }
@@ -136,7 +453,7 @@ impl Plugin for LuaGarden {
             let runtime_success = self.runtime.reset();
         
             if !runtime_success {
-                runtime_data.set_state(RuntimeState::Offline);
+                runtime_data.set_state(self.failed_runtime_state());
             }
         }
     }
@@ -144,29 +461,30 @@ impl Plugin for LuaGarden {
     fn process(
         &mut self,
         buffer: &mut Buffer,
-        _aux: &mut AuxiliaryBuffers,
-        _context: &mut impl ProcessContext<Self>,
+        aux: &mut AuxiliaryBuffers,
+        context: &mut impl ProcessContext<Self>,
     ) -> ProcessStatus {
         let runtime_data_lock = self.runtime_data.clone();
         let mut runtime_data = runtime_data_lock.write().unwrap();
         let interface_data = self.interface_data.read().unwrap().clone();
 
         runtime_data.update_from_interface(&interface_data);
+        self.runtime.set_memory_limit(runtime_data.memory_limit);
+        self.collect_events(&runtime_data, context);
 
         match runtime_data.state {
             RuntimeState::Refresh => {
-                self.refresh_runtime_module(&interface_data);
-                runtime_data.set_state(RuntimeState::Online);
-    
-                let runtime_success = self.runtime.init(None);            
-                if !runtime_success {
-                    runtime_data.set_state(RuntimeState::Offline);
-                }
-    
-                let runtime_success = self.runtime.reset();
-            
-                if !runtime_success {
-                    runtime_data.set_state(RuntimeState::Offline);
+                let load_success = self.refresh_runtime_module(&interface_data);
+                let init_success = load_success && self.runtime.init(None);
+                let reset_success = init_success && self.runtime.reset();
+
+                if reset_success {
+                    self.runtime.begin_crossfade();
+                    runtime_data.set_state(RuntimeState::Online);
+                } else if self.runtime.restore_previous_module() {
+                    runtime_data.set_state(RuntimeState::Online);
+                } else {
+                    runtime_data.set_state(self.failed_runtime_state());
                 }
             },
             RuntimeState::Clear => {
@@ -176,17 +494,38 @@ impl Plugin for LuaGarden {
             _ => ()
         }
 
+        // Modules that stopped or were replaced don't get to turn their notes off, so do it for them
+        for event in self.runtime.take_released_notes().note_offs() {
+            context.send_event(event.to_note_event());
+        }
+
         if runtime_data.state == RuntimeState::Online {
             self.runtime.set_clip(runtime_data.clip);
             self.runtime.set_input_noise(runtime_data.input_noise);
-            let runtime_success = self.runtime.run(buffer);
+            self.runtime.set_watchdog_budget(runtime_data.watchdog_budget);
+            self.runtime.set_crossfade_ms(runtime_data.crossfade_ms);
+            self.apply_macros();
+            let sidechain = match aux.inputs.first() {
+                Some(s) => s.as_slice_immutable(),
+                None => &[]
+            };
+            let runtime_success = self.trigger_runtime() && self.runtime.run(buffer, sidechain, &self.midi_events, context.transport());
     
-            if !runtime_success {
-                runtime_data.set_state(RuntimeState::Offline);
+            if runtime_success {
+                for event in self.runtime.get_midi_output() {
+                    context.send_event(event.to_note_event());
+                }
+            } else {
+                runtime_data.set_state(self.failed_runtime_state());
             }
         }
+
+        if runtime_data.state == RuntimeState::Bypass {
+            self.runtime.bypass(buffer);
+        }
         
         runtime_data.update_from_runtime(&mut self.runtime, &interface_data);
+        self.update_macro_mapping(&mut runtime_data);
 
         self.update_runtime_status(&mut runtime_data);
 
@@ -201,7 +540,7 @@ impl ClapPlugin for LuaGarden {
     const CLAP_SUPPORT_URL: Option<&'static str> = None;
 
     // Don't forget to change these features
-    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Stereo];
+    const CLAP_FEATURES: &'static [ClapFeature] = &[ClapFeature::AudioEffect, ClapFeature::Instrument, ClapFeature::Synthesizer, ClapFeature::Stereo, ClapFeature::Mono, ClapFeature::Surround];
 }
 
 impl Vst3Plugin for LuaGarden {
@@ -209,7 +548,7 @@ impl Vst3Plugin for LuaGarden {
 
     // And also don't forget to change these categories
     const VST3_SUBCATEGORIES: &'static [Vst3SubCategory] =
-        &[Vst3SubCategory::Fx, Vst3SubCategory::Tools];
+        &[Vst3SubCategory::Fx, Vst3SubCategory::Instrument, Vst3SubCategory::Synth, Vst3SubCategory::Tools];
 }
 
 nih_export_clap!(LuaGarden);
@@ -6,6 +6,10 @@
 -- MODULE_NAME - This module's name.
 -- MODULE_AUTHORS - Who made this module.
 -- MODULE_ABOUT - A desciption of the module.
+--
+-- In a workspace, require("voices") loads voices.lua and require("lib.delay") loads lib/delay.lua
+-- (or lib/delay/init.lua) from the workspace folder.
+-- Any module can also require files from the user library folder, set in the Mode menu.
 
 MODULE_NAME = "Empty module";
 MODULE_AUTHORS = "???";
@@ -4,8 +4,24 @@
 -- Available globals:
 -- SAMPLE_RATE - The sample rate the plugin is running at.
 -- CHANNELS - The channels the plugin is running at.
+-- INPUT_CHANNELS - How many of those channels carry input. Mono to Stereo only fills the first, Instrument none.
+-- CHANNEL_NAMES - The name of each channel, like "Left" or "LFE".
+-- LAYOUT - The name of the channel layout, like "Stereo" or "5.1".
 -- BUFFER - Sample buffer, indexed by channel, then sample.
 -- BUFFER_SIZE - The length of each sample buffer.
+-- SIDECHAIN - Read-only sidechain input, indexed by channel, then sample.
+-- MIDI_EVENTS - MIDI events in this buffer, in order. Each has a type ("note_on", "note_off", "pitch_bend" or "cc"),
+--               a sample and offset, a channel and note and velocity, or cc and value.
+--               Use runtime.iterate_midi to get the events landing on each sample.
+-- TEMPO - The host's tempo in beats per minute.
+-- TIME_SIG - The host's time signature, as TIME_SIG.numerator and TIME_SIG.denominator.
+-- PLAYING - Whether the host is playing.
+-- POS_SAMPLES - The song position at the start of this buffer, in samples.
+-- POS_BEATS - The song position at the start of this buffer, in beats.
+--             runtime.iterate hands tick the beat position of each sample.
+-- BAR_START - Where the current bar started, in beats.
+--
+-- Send MIDI with midi.send_note_on(sample, note, velocity, channel), midi.send_note_off(...) and midi.send_cc(sample, cc, value, channel).
 
 runtime.iterate(function(sample)
     for channel = 1, BUFFER.channels do 
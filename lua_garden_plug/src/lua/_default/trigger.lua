@@ -1,5 +1,8 @@
 -- trigger.lua
--- Trigger envelopes and stuff here. Currently not implemented.
+-- Trigger envelopes and stuff here. Runs before run.lua, once for each trigger.
+-- Triggers come from the Trigger button, the Trigger parameter and incoming MIDI notes.
 -- 
 -- Available globals:
 -- SAMPLE_RATE - The sample rate the plugin is running at.
+-- TRIGGER_SAMPLE - The sample in the next buffer the trigger lands on, starting at 1 like BUFFER and MIDI event samples.
+-- TRIGGER_OFFSET - The same sample, starting at 0 like MIDI event offsets.
@@ -1,9 +1,3 @@
 -- ==== --
 -- FOOTER
 -- ↓↓↓↓ --
-
-for c = 1, BUFFER.channels do 
-    for b = 1, BUFFER.size do
-        BUFFER_RAW[c][b] = BUFFER[c][b];
-    end
-end
\ No newline at end of file
@@ -1,13 +1,20 @@
--- Ensure globals have values and we have a proper buffer.
+-- Ensure globals have values. BUFFER and SIDECHAIN are provided by the runtime.
 SAMPLE_RATE = SAMPLE_RATE or 0;
 CHANNELS = CHANNELS or 0;
-BUFFER_RAW = BUFFER_RAW or { };
-BUFFER_SIZE = BUFFER_SIZE or { };
-BUFFER = Buffer:copy(BUFFER_RAW, BUFFER_SIZE, CHANNELS);
+BUFFER_SIZE = BUFFER_SIZE or 0;
 INPUT_NOISE = INPUT_NOISE or false;
+MIDI_EVENTS = MIDI_EVENTS or { };
+TEMPO = TEMPO or 120.0;
+TIME_SIG = TIME_SIG or { };
+TIME_SIG.numerator = TIME_SIG.numerator or 4;
+TIME_SIG.denominator = TIME_SIG.denominator or 4;
+PLAYING = PLAYING or false;
+POS_SAMPLES = POS_SAMPLES or 0;
+POS_BEATS = POS_BEATS or 0.0;
+BAR_START = BAR_START or 0.0;
 
 Parameter.update_values_from_global();
 
 -- ↑↑↑↑ --
 -- HEADER
--- ==== --
\ No newline at end of file
+-- ==== --
@@ -1,3 +1,7 @@
+-- Ensure globals have values. TRIGGER_SAMPLE and TRIGGER_OFFSET are provided by the runtime.
+SAMPLE_RATE = SAMPLE_RATE or 0;
+TRIGGER_SAMPLE = TRIGGER_SAMPLE or 1;
+TRIGGER_OFFSET = TRIGGER_OFFSET or 0;
 
 -- ↑↑↑↑ --
 -- HEADER
@@ -0,0 +1,24 @@
+-- Sends MIDI out of the plugin. Events are queued and handed to the host after run.
+-- Samples start at 1, like BUFFER. Channels run from 1 to 16 and default to 1.
+
+MIDI_OUT = { };
+MIDI_OUT_COUNT = 0;
+
+midi = { };
+
+function midi.send_note_on (sample, note, velocity, channel)
+    midi.queue({ type = "note_on", sample = sample, note = note, velocity = velocity or 1.0, channel = channel or 1 });
+end
+
+function midi.send_note_off (sample, note, velocity, channel)
+    midi.queue({ type = "note_off", sample = sample, note = note, velocity = velocity or 0.0, channel = channel or 1 });
+end
+
+function midi.send_cc (sample, cc, value, channel)
+    midi.queue({ type = "cc", sample = sample, cc = cc, value = value, channel = channel or 1 });
+end
+
+function midi.queue (event)
+    MIDI_OUT_COUNT = MIDI_OUT_COUNT + 1;
+    MIDI_OUT[MIDI_OUT_COUNT] = event;
+end
@@ -1,6 +1,7 @@
 -- Parameters for smoothed values.
 
 PARAMETERS = { };
+PARAMETER_COUNT = 0;
 PARAMETER_VALUE_UPDATES = nil;
 
 Parameter = {
@@ -12,6 +13,9 @@ Parameter = {
     step_size = 0,
     smoothing_ms = 10.0,
 
+    order = 0,
+    slot = nil,
+
     old_value = 0,
     set_tick = 0
 }
@@ -43,9 +47,16 @@ function Parameter:register ()
     end
     
     runtime.log(string.format("Registered parameter \"%s\".", self.name));
+    PARAMETER_COUNT = PARAMETER_COUNT + 1;
+    self.order = PARAMETER_COUNT;
     PARAMETERS[self.name] = self;
 end
 
+-- Puts the parameter on a specific host macro slot, starting at 1. Otherwise slots are filled in registration order.
+function Parameter:set_slot (slot)
+    self.slot = slot;
+end
+
 function Parameter:set_value (value)
     self.value = math.clamp(value, self.min, self.max);
     self.old_value = self:get_smoothed();
@@ -78,7 +89,10 @@ function Parameter.update_values_from_global ()
     end
 
     for p_name, p_value in pairs(PARAMETER_VALUE_UPDATES) do
-        PARAMETERS[p_name]:set_value(p_value);
+        -- Values can outlive the parameter, when they're restored into a module that changed.
+        if PARAMETERS[p_name] ~= nil then
+            PARAMETERS[p_name]:set_value(p_value);
+        end
     end
 
     PARAMETER_VALUE_UPDATES = nil;
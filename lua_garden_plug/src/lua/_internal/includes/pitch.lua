@@ -20,6 +20,11 @@ function pitch.note_hz (note)
 end
 
 
+-- MIDI notes count from C-1, so A4 is note 69.
+function pitch.midi_note_hz (note)
+    return pitch.tuning_frequency * 2.0 ^ ((note - 69) / pitch.notes_per_octave);
+end
+
 function pitch.note_to_playback (note)
     if note == 0.0 then
        return 1.0;
@@ -10,8 +10,42 @@ function runtime.log (log)
     LOGS[LOG_COUNT] = tostring(log);
 end
 
+-- How far the song moves each sample, in beats. 0 while the host isn't playing.
+function runtime.beats_per_sample ()
+    if not PLAYING or SAMPLE_RATE <= 0 then
+        return 0.0;
+    end
+
+    return TEMPO / 60.0 / SAMPLE_RATE;
+end
+
+function runtime.beat_at (sample)
+    return POS_BEATS + (sample - 1) * runtime.beats_per_sample();
+end
+
 function runtime.iterate (tick)
     local start_tick = TICK;
+    local beats_per_sample = runtime.beats_per_sample();
+    for b = 1, BUFFER.size do
+        TICK = start_tick + b
+
+        if INPUT_NOISE then
+            for c = 1, BUFFER.channels do 
+                BUFFER[c][b] = gen.noise() * 0.1;
+            end
+        end
+
+        tick(b, POS_BEATS + (b - 1) * beats_per_sample);
+    end
+end
+
+local no_events = { };
+
+-- Like runtime.iterate, but also hands tick the MIDI events landing on each sample, before the beat.
+function runtime.iterate_midi (tick)
+    local start_tick = TICK;
+    local beats_per_sample = runtime.beats_per_sample();
+    local e = 1;
     for b = 1, BUFFER.size do
         TICK = start_tick + b
 
@@ -21,6 +55,16 @@ function runtime.iterate (tick)
             end
         end
 
-        tick(b);
+        local events = no_events;
+        while MIDI_EVENTS[e] ~= nil and MIDI_EVENTS[e].sample <= b do
+            if events == no_events then
+                events = { };
+            end
+
+            events[#events + 1] = MIDI_EVENTS[e];
+            e = e + 1;
+        end
+
+        tick(b, events, POS_BEATS + (b - 1) * beats_per_sample);
     end
 end
\ No newline at end of file
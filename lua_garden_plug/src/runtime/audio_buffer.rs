@@ -0,0 +1,166 @@
+use mlua::prelude::*;
+use super::utils;
+
+const LUA_SIZE_KEY: &str = "size";
+const LUA_CHANNELS_KEY: &str = "channels";
+
+// Rust-owned audio buffer exposed to Lua as BUFFER and SIDECHAIN.
+// Indexed by channel, then sample. BUFFER[c] returns a channel view, BUFFER[c][s] a sample.
+pub struct LuaBuffer {
+    channels: Vec<LuaAnyUserData>,
+    size: usize,
+    read_only: bool
+}
+
+// A single channel of a LuaBuffer. Samples are preallocated and only grow.
+pub struct LuaBufferChannel {
+    samples: Vec<f32>,
+    size: usize,
+    read_only: bool
+}
+
+impl LuaBuffer {
+    pub fn new() -> LuaBuffer {
+        Self {
+            channels: Vec::new(),
+            size: 0,
+            read_only: false
+        }
+    }
+
+    // A buffer scripts can read but not write to.
+    pub fn new_read_only() -> LuaBuffer {
+        Self {
+            channels: Vec::new(),
+            size: 0,
+            read_only: true
+        }
+    }
+
+    pub fn channels(&self) -> usize {
+        return self.channels.len();
+    }
+
+    pub fn size(&self) -> usize {
+        return self.size;
+    }
+
+    // Ensures the buffer holds enough channels and samples. Only allocates when growing.
+    pub fn prepare(&mut self, lua: &Lua, channels: usize, size: usize) -> LuaResult<()> {
+        while self.channels.len() < channels {
+            let channel = lua.create_userdata(LuaBufferChannel::new(size, self.read_only))?;
+            self.channels.push(channel);
+        }
+        self.channels.truncate(channels);
+
+        for channel in &self.channels {
+            channel.borrow_mut::<LuaBufferChannel>()?.resize(size);
+        }
+        self.size = size;
+
+        Ok(())
+    }
+
+    pub fn write_from<T: AsRef<[f32]>>(&self, samples: &[T]) -> LuaResult<()> {
+        for (c, channel_samples) in samples.iter().enumerate() {
+            match self.channels.get(c) {
+                Some(channel) => channel.borrow_mut::<LuaBufferChannel>()?.write_from(channel_samples.as_ref()),
+                None => ()
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read_into<T: AsMut<[f32]>>(&self, samples: &mut [T], clip: bool) -> LuaResult<()> {
+        for (c, channel_samples) in samples.iter_mut().enumerate() {
+            match self.channels.get(c) {
+                Some(channel) => channel.borrow::<LuaBufferChannel>()?.read_into(channel_samples.as_mut(), clip),
+                None => ()
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl LuaBufferChannel {
+    pub fn new(size: usize, read_only: bool) -> LuaBufferChannel {
+        Self {
+            samples: vec![0.0; size],
+            size: size,
+            read_only: read_only
+        }
+    }
+
+    pub fn resize(&mut self, size: usize) {
+        if self.samples.len() < size {
+            self.samples.resize(size, 0.0);
+        }
+        self.size = size;
+    }
+
+    pub fn write_from(&mut self, samples: &[f32]) {
+        let length = usize::min(samples.len(), self.size);
+        self.samples[..length].copy_from_slice(&samples[..length]);
+    }
+
+    pub fn read_into(&self, samples: &mut [f32], clip: bool) {
+        let length = usize::min(samples.len(), self.size);
+
+        if clip {
+            for s in 0..length {
+                samples[s] = utils::clip(self.samples[s]);
+            }
+        } else {
+            samples[..length].copy_from_slice(&self.samples[..length]);
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<f32> {
+        // Lua indexes start at 1
+        if index < 1 || index > self.size { return None; }
+
+        return Some(self.samples[index - 1]);
+    }
+
+    fn set(&mut self, index: usize, value: f32) -> LuaResult<()> {
+        if self.read_only {
+            return Err(LuaError::runtime("This buffer is read-only."));
+        }
+
+        // Lua indexes start at 1
+        if index < 1 || index > self.size {
+            return Err(LuaError::runtime(format!("Sample index {index} is out of range (1 to {size}).", index = index, size = self.size)));
+        }
+
+        self.samples[index - 1] = value;
+        Ok(())
+    }
+}
+
+impl LuaUserData for LuaBuffer {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get(LUA_SIZE_KEY, |_, this| Ok(this.size));
+        fields.add_field_method_get(LUA_CHANNELS_KEY, |_, this| Ok(this.channels.len()));
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(LuaMetaMethod::Index, |_, this, index: usize| {
+            // Lua indexes start at 1
+            if index < 1 { return Ok(None); }
+
+            return Ok(this.channels.get(index - 1).cloned());
+        });
+
+        methods.add_meta_method(LuaMetaMethod::Len, |_, this, ()| Ok(this.channels.len()));
+    }
+}
+
+impl LuaUserData for LuaBufferChannel {
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(LuaMetaMethod::Index, |_, this, index: usize| Ok(this.get(index)));
+        methods.add_meta_method_mut(LuaMetaMethod::NewIndex, |_, this, (index, value): (usize, f32)| this.set(index, value));
+        methods.add_meta_method(LuaMetaMethod::Len, |_, this, ()| Ok(this.size));
+    }
+}
@@ -0,0 +1,58 @@
+pub const BYPASS_FADE_MS: f32 = 10.0;
+
+// Fades from the module's last output to the dry input, so bypassing a failed module doesn't click.
+pub struct BypassFade {
+    held: Vec<f32>,
+
+    samples_left: usize,
+    samples_total: usize
+}
+
+impl BypassFade {
+    pub fn new() -> BypassFade {
+        Self {
+            held: Vec::new(),
+
+            samples_left: 0,
+            samples_total: 0
+        }
+    }
+
+    // Remembers the last output sample of each channel.
+    pub fn hold(&mut self, output: &[&mut [f32]]) {
+        self.held.resize(output.len(), 0.0);
+
+        for (c, channel) in output.iter().enumerate() {
+            match channel.last() {
+                Some(sample) => self.held[c] = *sample,
+                None => ()
+            }
+        }
+    }
+
+    pub fn begin(&mut self, samples: usize) {
+        self.samples_left = samples;
+        self.samples_total = samples;
+    }
+
+    pub fn process(&mut self, buffer: &mut [&mut [f32]]) {
+        if self.samples_left == 0 { return; }
+
+        let size = buffer.first().map_or(0, |c| c.len());
+        let done = self.samples_total - self.samples_left;
+
+        for (c, channel) in buffer.iter_mut().enumerate() {
+            let held = match self.held.get(c) {
+                Some(h) => *h,
+                None => continue
+            };
+
+            for s in 0..size {
+                let t = f32::min((done + s) as f32 / self.samples_total as f32, 1.0);
+                channel[s] = held * (1.0 - t) + channel[s] * t;
+            }
+        }
+
+        self.samples_left = self.samples_left.saturating_sub(size);
+    }
+}
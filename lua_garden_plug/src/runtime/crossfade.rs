@@ -0,0 +1,122 @@
+use mlua::prelude::*;
+use nih_plug::prelude::Transport;
+use super::{midi::{ ActiveNotes, MidiEvent }, module::RuntimeModule};
+
+pub const DEFAULT_CROSSFADE_MS: f32 = 50.0;
+pub const MIN_CROSSFADE_MS: f32 = 0.0;
+pub const MAX_CROSSFADE_MS: f32 = 500.0;
+
+// Keeps the previous module around after a reload, so it can be faded out or fallen back to.
+pub struct Crossfade {
+    module: Option<RuntimeModule>,
+    buffer: Vec<Vec<f32>>,
+    released_notes: ActiveNotes,
+
+    samples_left: usize,
+    samples_total: usize
+}
+
+impl Crossfade {
+    pub fn new() -> Crossfade {
+        Self {
+            module: None,
+            buffer: Vec::new(),
+            released_notes: ActiveNotes::new(),
+
+            samples_left: 0,
+            samples_total: 0
+        }
+    }
+
+    // Holds on to a module without fading it yet.
+    pub fn stash(&mut self, module: RuntimeModule) {
+        self.module = Some(module);
+        self.samples_left = 0;
+        self.samples_total = 0;
+    }
+
+    pub fn take(&mut self) -> Option<RuntimeModule> {
+        self.samples_left = 0;
+        self.samples_total = 0;
+
+        return self.module.take();
+    }
+
+    // Drops the module. Notes it left on are kept to be released.
+    pub fn clear(&mut self) {
+        match self.take() {
+            Some(mut module) => module.release_notes(&mut self.released_notes),
+            None => ()
+        }
+    }
+
+    pub fn release_notes(&mut self, released: &mut ActiveNotes) {
+        released.merge(&mut self.released_notes);
+    }
+
+    pub fn begin(&mut self, samples: usize) {
+        if samples == 0 {
+            self.clear();
+            return;
+        }
+
+        self.samples_left = samples;
+        self.samples_total = samples;
+    }
+
+    pub fn is_active(&self) -> bool {
+        return self.module.is_some() && self.samples_left > 0;
+    }
+
+    // Runs the outgoing module on a copy of the input. Call before the incoming module processes the buffer.
+    pub fn run<S: AsRef<[f32]>>(&mut self, input: &[&mut [f32]], sidechain: &[S], midi_events: &[MidiEvent], transport: &Transport, input_noise: bool, clip: bool) -> Option<LuaResult<Vec<String>>> {
+        if !self.is_active() { return None; }
+
+        let size = input.first().map_or(0, |c| c.len());
+        self.buffer.resize_with(input.len(), Vec::new);
+        for (c, channel) in input.iter().enumerate() {
+            self.buffer[c].resize(size, 0.0);
+            self.buffer[c].copy_from_slice(channel);
+        }
+
+        return match &mut self.module {
+            Some(module) => Some(module.run(self.buffer.as_mut_slice(), sidechain, midi_events, transport, input_noise, clip)),
+            None => None
+        };
+    }
+
+    // Collects the outgoing module's MIDI, so notes it's still holding get their note offs.
+    pub fn process_midi_output(&mut self, output: &mut Vec<MidiEvent>, buffer_size: usize, max_events: usize) -> LuaResult<()> {
+        if !self.is_active() { return Ok(()); }
+
+        return match &mut self.module {
+            Some(module) => module.process_midi_output(output, buffer_size, max_events),
+            None => Ok(())
+        };
+    }
+
+    // Fades the outgoing module's output into the incoming module's output.
+    pub fn mix(&mut self, output: &mut [&mut [f32]]) {
+        if !self.is_active() { return; }
+
+        let size = output.first().map_or(0, |c| c.len());
+        let done = self.samples_total - self.samples_left;
+
+        for (c, channel) in output.iter_mut().enumerate() {
+            let previous = match self.buffer.get(c) {
+                Some(p) => p,
+                None => continue
+            };
+
+            for s in 0..size {
+                let t = f32::min((done + s) as f32 / self.samples_total as f32, 1.0);
+                channel[s] = channel[s] * t + previous[s] * (1.0 - t);
+            }
+        }
+
+        self.samples_left = self.samples_left.saturating_sub(size);
+        if self.samples_left == 0 {
+            self.clear();
+        }
+    }
+}
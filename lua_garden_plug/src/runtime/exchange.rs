@@ -0,0 +1,307 @@
+use std::{ fs, io::{ Read, Write } };
+use base64::{ engine::general_purpose::URL_SAFE_NO_PAD, Engine as _ };
+use flate2::{ read::DeflateDecoder, write::DeflateEncoder, Compression, Crc };
+use serde::{ Deserialize, Serialize };
+
+use super::module_content::ModuleContent;
+
+pub const GARDEN_EXTENSION: &str = "garden";
+pub const FORMAT_VERSION: u8 = 1;
+const MAGIC: &[u8; 4] = b"GRDN";
+const SHARE_PREFIX: &str = "garden:";
+const LENGTH_SIZE: usize = 4;
+const CHECKSUM_SIZE: usize = 4;
+// Decompressed modules larger than this are refused, so a crafted file can't exhaust memory.
+pub const MAX_MODULE_BYTES: u64 = 16 * 1024 * 1024;
+
+// Describes an exchanged module without having to run it.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModuleMetadata {
+    pub name: String,
+    pub authors: String,
+    pub about: String
+}
+
+// A module packed for sharing, as a string or a .garden file.
+// Layout: magic, format version, metadata length and json, content length and deflated json, crc32 of everything before it.
+pub struct ModuleExchange {
+    pub version: u8,
+    pub metadata: ModuleMetadata,
+    pub content: ModuleContent
+}
+
+impl ModuleMetadata {
+    pub fn new(name: String, authors: String, about: String) -> ModuleMetadata {
+        Self {
+            name: name,
+            authors: authors,
+            about: about
+        }
+    }
+
+    pub fn unknown() -> ModuleMetadata {
+        return ModuleMetadata::new(String::from("???"), String::from("???"), String::new());
+    }
+}
+
+impl ModuleExchange {
+    pub fn new(content: ModuleContent, metadata: ModuleMetadata) -> ModuleExchange {
+        Self {
+            version: FORMAT_VERSION,
+            metadata: metadata,
+            content: content
+        }
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, String> {
+        let metadata = match serde_json::to_vec(&self.metadata) {
+            Ok(m) => m,
+            Err(e) => return Err(format!("Couldn't serialize metadata: {}", e))
+        };
+        let content = match serde_json::to_vec(&self.content) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't serialize module: {}", e))
+        };
+
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        let compressed = match encoder.write_all(&content).and_then(|_| encoder.finish()) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't compress module: {}", e))
+        };
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&metadata);
+        bytes.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&compressed);
+
+        let checksum = ModuleExchange::checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        return Ok(bytes);
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<ModuleExchange, String> {
+        if bytes.len() < MAGIC.len() + 1 + CHECKSUM_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(format!("This isn't a {} module.", GARDEN_EXTENSION));
+        }
+
+        let version = bytes[MAGIC.len()];
+        if version > FORMAT_VERSION {
+            return Err(format!("This module uses format version {version}, which is newer than this build supports ({supported}).", 
+                version = version, 
+                supported = FORMAT_VERSION));
+        }
+
+        let (body, checksum) = bytes.split_at(bytes.len() - CHECKSUM_SIZE);
+        let checksum = u32::from_le_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+        if ModuleExchange::checksum(body) != checksum {
+            return Err(format!("Checksum mismatch. The module is damaged or was changed."));
+        }
+
+        let mut position = MAGIC.len() + 1;
+        let metadata = ModuleExchange::read_block(body, &mut position)?;
+        let compressed = ModuleExchange::read_block(body, &mut position)?;
+
+        let metadata: ModuleMetadata = match serde_json::from_slice(metadata) {
+            Ok(m) => m,
+            Err(e) => return Err(format!("Couldn't parse metadata: {}", e))
+        };
+
+        let mut content = Vec::new();
+        match DeflateDecoder::new(compressed).take(MAX_MODULE_BYTES + 1).read_to_end(&mut content) {
+            Ok(_) => (),
+            Err(e) => return Err(format!("Couldn't decompress module: {}", e))
+        }
+        if content.len() as u64 > MAX_MODULE_BYTES {
+            return Err(format!("The module is larger than {} MB when decompressed.", MAX_MODULE_BYTES / (1024 * 1024)));
+        }
+
+        let content: ModuleContent = match serde_json::from_slice(&content) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't parse module: {}", e))
+        };
+
+        return Ok(Self {
+            version: version,
+            metadata: metadata,
+            content: content
+        });
+    }
+
+    pub fn to_share_string(&self) -> Result<String, String> {
+        let bytes = self.to_bytes()?;
+
+        return Ok(format!("{prefix}{encoded}", prefix = SHARE_PREFIX, encoded = URL_SAFE_NO_PAD.encode(bytes)));
+    }
+
+    // Also reads the older plain base64 strings, which have no version or metadata.
+    pub fn from_share_string(share_string: &str) -> Result<ModuleExchange, String> {
+        let share_string = share_string.trim();
+
+        match share_string.strip_prefix(SHARE_PREFIX) {
+            Some(encoded) => {
+                let bytes = match URL_SAFE_NO_PAD.decode(encoded) {
+                    Ok(b) => b,
+                    Err(e) => return Err(format!("The share string isn't valid base64: {}", e))
+                };
+
+                return ModuleExchange::from_bytes(&bytes);
+            },
+            None => {
+                let content = ModuleContent::from_base64(share_string)?;
+
+                return Ok(Self {
+                    version: 0,
+                    metadata: ModuleMetadata::unknown(),
+                    content: content
+                });
+            }
+        }
+    }
+
+    pub fn write_to_file(&self, path: &str) -> Result<(), String> {
+        let bytes = self.to_bytes()?;
+
+        match fs::write(path, bytes) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(format!("Couldn't write {}: {}", path, e))
+        }
+    }
+
+    pub fn read_from_file(path: &str) -> Result<ModuleExchange, String> {
+        match fs::read(path) {
+            Ok(bytes) => return ModuleExchange::from_bytes(&bytes),
+            Err(e) => return Err(format!("Couldn't read {}: {}", path, e))
+        }
+    }
+
+    fn read_block<'a>(body: &'a [u8], position: &mut usize) -> Result<&'a [u8], String> {
+        if body.len() < *position + LENGTH_SIZE {
+            return Err(format!("The module ends early."));
+        }
+
+        let length_bytes = &body[*position..*position + LENGTH_SIZE];
+        let length = u32::from_le_bytes([length_bytes[0], length_bytes[1], length_bytes[2], length_bytes[3]]) as usize;
+        *position += LENGTH_SIZE;
+
+        if body.len() < *position + length {
+            return Err(format!("The module ends early."));
+        }
+
+        let block = &body[*position..*position + length];
+        *position += length;
+
+        return Ok(block);
+    }
+
+    fn checksum(bytes: &[u8]) -> u32 {
+        let mut crc = Crc::new();
+        crc.update(bytes);
+
+        return crc.sum();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::module_content::tests::test_content;
+
+    fn test_exchange() -> ModuleExchange {
+        let metadata = ModuleMetadata::new(String::from("Test"), String::from("Someone"), String::from("For testing."));
+
+        return ModuleExchange::new(test_content(), metadata);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        let exchange = test_exchange();
+        let decoded = ModuleExchange::from_bytes(&exchange.to_bytes().unwrap()).ok().unwrap();
+
+        assert_eq!(decoded.version, FORMAT_VERSION);
+        assert!(decoded.metadata == exchange.metadata);
+        assert!(decoded.content == exchange.content);
+    }
+
+    #[test]
+    fn share_string_round_trip() {
+        let exchange = test_exchange();
+        let share_string = exchange.to_share_string().unwrap();
+        let decoded = ModuleExchange::from_share_string(&share_string).ok().unwrap();
+
+        assert!(share_string.starts_with(SHARE_PREFIX));
+        assert!(decoded.metadata == exchange.metadata);
+        assert!(decoded.content == exchange.content);
+    }
+
+    #[test]
+    fn corrupted_checksum_is_rejected() {
+        let mut bytes = test_exchange().to_bytes().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 0xff;
+
+        assert!(ModuleExchange::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn corrupted_content_is_rejected() {
+        let mut bytes = test_exchange().to_bytes().unwrap();
+        let middle = bytes.len() / 2;
+        bytes[middle] ^= 0xff;
+
+        assert!(ModuleExchange::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn truncated_input_is_rejected() {
+        let bytes = test_exchange().to_bytes().unwrap();
+
+        for length in [0, MAGIC.len(), MAGIC.len() + 1, bytes.len() / 2, bytes.len() - 1] {
+            assert!(ModuleExchange::from_bytes(&bytes[..length]).is_err(), "accepted {} of {} bytes", length, bytes.len());
+        }
+    }
+
+    #[test]
+    fn truncated_block_with_valid_checksum_is_rejected() {
+        let bytes = test_exchange().to_bytes().unwrap();
+
+        // Cut into the content block, then checksum what's left so only the block lengths are wrong.
+        let mut truncated = bytes[..bytes.len() - CHECKSUM_SIZE - 4].to_vec();
+        let checksum = ModuleExchange::checksum(&truncated);
+        truncated.extend_from_slice(&checksum.to_le_bytes());
+
+        assert!(ModuleExchange::from_bytes(&truncated).is_err());
+    }
+
+    #[test]
+    fn oversized_content_is_rejected() {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::best());
+        encoder.write_all(&vec![b' '; MAX_MODULE_BYTES as usize + 1]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let metadata = serde_json::to_vec(&ModuleMetadata::unknown()).unwrap();
+
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(FORMAT_VERSION);
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&metadata);
+        bytes.extend_from_slice(&(compressed.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&compressed);
+        let checksum = ModuleExchange::checksum(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        assert!(ModuleExchange::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn legacy_share_string_decodes() {
+        let content = test_content();
+        let decoded = ModuleExchange::from_share_string(&content.to_base64()).ok().unwrap();
+
+        assert_eq!(decoded.version, 0);
+        assert!(decoded.content == content);
+    }
+}
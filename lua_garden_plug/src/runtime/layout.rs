@@ -0,0 +1,55 @@
+use nih_plug::prelude::*;
+
+pub const MONO_CHANNEL_NAMES: [&str; 1] = ["Mono"];
+pub const STEREO_CHANNEL_NAMES: [&str; 2] = ["Left", "Right"];
+pub const QUAD_CHANNEL_NAMES: [&str; 4] = ["Front Left", "Front Right", "Rear Left", "Rear Right"];
+pub const SURROUND_5_1_CHANNEL_NAMES: [&str; 6] = ["Left", "Right", "Center", "LFE", "Rear Left", "Rear Right"];
+pub const SURROUND_7_1_CHANNEL_NAMES: [&str; 8] = ["Left", "Right", "Center", "LFE", "Rear Left", "Rear Right", "Side Left", "Side Right"];
+
+pub const MONO_NAME: &str = "Mono";
+pub const MONO_TO_STEREO_NAME: &str = "Mono to Stereo";
+pub const STEREO_NAME: &str = "Stereo";
+pub const QUAD_NAME: &str = "Quad";
+pub const SURROUND_5_1_NAME: &str = "5.1";
+pub const SURROUND_7_1_NAME: &str = "7.1";
+pub const INSTRUMENT_NAME: &str = "Instrument";
+const UNKNOWN_NAME: &str = "Unknown";
+
+// The channel layout the host picked, as modules see it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChannelLayout {
+    pub name: &'static str,
+    pub input_channels: usize,
+    pub channel_names: &'static [&'static str]
+}
+
+impl ChannelLayout {
+    pub const fn stereo() -> ChannelLayout {
+        return Self {
+            name: STEREO_NAME,
+            input_channels: 2,
+            channel_names: &STEREO_CHANNEL_NAMES
+        };
+    }
+
+    // Channel names follow the output, since modules process in place.
+    pub fn from_audio_io_layout(layout: &AudioIOLayout) -> ChannelLayout {
+        let input_channels = layout.main_input_channels.map_or(0, |c| c.get() as usize);
+        let output_channels = layout.main_output_channels.map_or(0, |c| c.get() as usize);
+
+        let channel_names: &'static [&'static str] = match output_channels {
+            1 => &MONO_CHANNEL_NAMES,
+            2 => &STEREO_CHANNEL_NAMES,
+            4 => &QUAD_CHANNEL_NAMES,
+            6 => &SURROUND_5_1_CHANNEL_NAMES,
+            8 => &SURROUND_7_1_CHANNEL_NAMES,
+            _ => &[]
+        };
+
+        return Self {
+            name: layout.names.layout.unwrap_or(UNKNOWN_NAME),
+            input_channels: input_channels,
+            channel_names: channel_names
+        };
+    }
+}
@@ -1,16 +1,30 @@
-use std::env;
+use std::{ collections::BTreeMap, env, fs, path::Path, sync::Arc };
 use super::module_content::ConstModuleContent;
 
-pub const INTERNAL_INCLUDES: [(&str, &str); 7] = [
+pub const INTERNAL_INCLUDES: [(&str, &str); 8] = [
     (include_str!("../lua/_internal/includes/runtime.lua"), "runtime.lua"),
     (include_str!("../lua/_internal/includes/math_extensions.lua"), "math_extensions.lua"),
     (include_str!("../lua/_internal/includes/pitch.lua"), "pitch.lua"),
     (include_str!("../lua/_internal/includes/buffer.lua"), "buffer.lua"),
     (include_str!("../lua/_internal/includes/parameter.lua"), "parameter.lua"),
     (include_str!("../lua/_internal/includes/gen.lua"), "gen.lua"),
-    (include_str!("../lua/_internal/includes/filters.lua"), "filters.lua")
+    (include_str!("../lua/_internal/includes/filters.lua"), "filters.lua"),
+    (include_str!("../lua/_internal/includes/midi.lua"), "midi.lua")
 ];
 
+pub const INIT_HEADER_PATH: &str = "headers/init_header.lua";
+pub const RESET_HEADER_PATH: &str = "headers/reset_header.lua";
+pub const TRIGGER_HEADER_PATH: &str = "headers/trigger_header.lua";
+pub const RUN_HEADER_PATH: &str = "headers/run_header.lua";
+pub const INIT_FOOTER_PATH: &str = "footers/init_footer.lua";
+pub const RESET_FOOTER_PATH: &str = "footers/reset_footer.lua";
+pub const TRIGGER_FOOTER_PATH: &str = "footers/trigger_footer.lua";
+pub const RUN_FOOTER_PATH: &str = "footers/run_footer.lua";
+const INTERNAL_CHUNK_PREFIX: &str = "lua_garden";
+const LUA_EXTENSION: &str = "lua";
+// How deep into subfolders lua files are looked for.
+const MAX_FOLDER_DEPTH: usize = 4;
+
 pub const INIT_HEADER: &str = include_str!("../lua/_internal/headers/init_header.lua");
 pub const RESET_HEADER: &str = include_str!("../lua/_internal/headers/reset_header.lua");
 pub const TRIGGER_HEADER: &str = include_str!("../lua/_internal/headers/trigger_header.lua");
@@ -25,6 +39,10 @@ pub const RESET_PATH: &str = "reset.lua";
 pub const TRIGGER_PATH: &str = "trigger.lua";
 pub const RUN_PATH: &str = "run.lua";
 pub const INTERFACE_PATH: &str = "interface.lua";
+pub const PRESETS_PATH: &str = "presets.json";
+pub const MANIFEST_PATH: &str = "module.toml";
+// Lives in the default workspaces folder.
+pub const BROWSER_PATH: &str = "browser.json";
 
 pub const DEFAULT_INIT_CONTENT: &str = include_str!("../lua/_default/init.lua");
 pub const DEFAULT_RESET_CONTENT: &str = include_str!("../lua/_default/reset.lua");
@@ -73,35 +91,94 @@ pub const MODULE_EXAMPLES: [(ConstModuleContent, &str); 4] = [
         "Waveshaper"),
 ];
 
-pub fn internal_includes() -> String {
-    let mut includes = String::new();
-
-    for include in INTERNAL_INCLUDES {
-        includes.push_str(&format!(
-            "\n\
-            -- ==== --\n\
-            -- INCLUDE {}\n\
-            -- ↓↓↓↓ --\n", include.1));
-        includes.push_str(include.0);
-        includes.push_str(&format!(
-            "\n\
-            -- ↑↑↑↑ --\n\
-            -- INCLUDE {}\n\
-            -- ==== --\n", include.1));
+// Chunk names starting with @ are reported by Lua as file names.
+pub fn chunk_name(path: Option<&str>, file: &str) -> String {
+    match path {
+        Some(p) => return format!("@{path}/{file}", path = p, file = file),
+        None => return format!("@{}", file)
     }
+}
 
-    return  includes;
+pub fn internal_chunk_name(file: &str) -> String {
+    return format!("@{prefix}/{file}", prefix = INTERNAL_CHUNK_PREFIX, file = file);
 }
 
 pub fn default_workspaces_path () -> String {
+    return workdir_path("workspaces");
+}
+
+// The shared user library sits next to the workspaces folder.
+pub fn default_library_path () -> String {
+    return workdir_path("library");
+}
+
+fn workdir_path(folder: &str) -> String {
     let mut workdir_path = match env::current_dir() {
         Ok(path) => path,
         Err(_e) => return String::new(),
     };
 
-    workdir_path.push("workspaces");
+    workdir_path.push(folder);
     match workdir_path.to_str() {
         Some(p) => return String::from(p),
         None => return String::new()
     }
-}
\ No newline at end of file
+}
+
+// Contents of lua files by path relative to their folder. Shared, so cloning the data that holds them stays cheap.
+pub type LuaFiles = Arc<BTreeMap<String, String>>;
+
+// Reads every lua file in a folder, so modules can require them without touching the disk on the audio thread.
+// Only reads files that are really inside the folder, even through links.
+pub fn read_lua_files(folder: &str) -> LuaFiles {
+    let mut contents = BTreeMap::new();
+
+    let root = match fs::canonicalize(folder) {
+        Ok(r) => r,
+        Err(_e) => return Arc::new(contents)
+    };
+
+    for file in lua_files(folder) {
+        let path = match fs::canonicalize(root.join(&file)) {
+            Ok(p) if p.starts_with(&root) => p,
+            _ => continue
+        };
+
+        match fs::read_to_string(path) {
+            Ok(c) => { contents.insert(file, c); },
+            Err(_e) => ()
+        }
+    }
+
+    return Arc::new(contents);
+}
+
+// All lua files in a folder and its subfolders, relative to it.
+pub fn lua_files(folder: &str) -> Vec<String> {
+    let mut files = Vec::new();
+    collect_lua_files(Path::new(folder), "", 0, &mut files);
+    files.sort();
+
+    return files;
+}
+
+fn collect_lua_files(folder: &Path, relative: &str, depth: usize, files: &mut Vec<String>) {
+    if depth > MAX_FOLDER_DEPTH { return; }
+
+    let entries = match fs::read_dir(folder) {
+        Ok(e) => e,
+        Err(_e) => return
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        let relative_name = if relative.is_empty() { name } else { format!("{}/{}", relative, name) };
+
+        if path.is_dir() {
+            collect_lua_files(&path, &relative_name, depth + 1, files);
+        } else if path.extension().map_or(false, |e| e == LUA_EXTENSION) {
+            files.push(relative_name);
+        }
+    }
+}
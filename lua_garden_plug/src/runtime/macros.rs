@@ -0,0 +1,162 @@
+use std::{ collections::BTreeMap, hash::{ DefaultHasher, Hash, Hasher } };
+
+use super::parameter::Parameter;
+
+pub const MACRO_SLOT_COUNT: usize = 16;
+
+const MAX_NAME_LENGTH: usize = 64;
+const ORDERED_CAPACITY: usize = 256;
+
+// A module parameter a macro slot controls.
+#[derive(Clone)]
+pub struct MacroSlot {
+    pub name: String,
+    pub min: f32,
+    pub max: f32,
+    pub step_size: f32,
+
+    mapped: bool
+}
+
+// Which module parameter each macro slot controls. Shared with the host params, so they can display it.
+pub struct MacroMapping {
+    slots: [MacroSlot; MACRO_SLOT_COUNT],
+    // Identifies the parameters the slots were mapped from, so the mapping only changes when they do.
+    signature: u64,
+    // Registration order and place of each parameter, kept around so remapping doesn't allocate.
+    ordered: Vec<(usize, usize, Option<usize>)>
+}
+
+impl MacroSlot {
+    fn new() -> MacroSlot {
+        Self {
+            name: String::with_capacity(MAX_NAME_LENGTH),
+            min: 0.0,
+            max: 1.0,
+            step_size: 0.0,
+
+            mapped: false
+        }
+    }
+
+    fn set(&mut self, parameter: &Parameter) {
+        self.name.clone_from(&parameter.name);
+        self.min = parameter.min;
+        self.max = parameter.max;
+        self.step_size = parameter.step_size;
+        self.mapped = true;
+    }
+
+    // Maps a normalized macro value onto the parameter's range.
+    pub fn to_value(&self, normalized: f32) -> f32 {
+        let value = self.min + f32::clamp(normalized, 0.0, 1.0) * (self.max - self.min);
+
+        if self.step_size <= 0.0 {
+            return value;
+        }
+
+        return f32::clamp((value / self.step_size).round() * self.step_size, self.min, self.max);
+    }
+
+    pub fn to_normalized(&self, value: f32) -> f32 {
+        if self.max <= self.min { return 0.0; }
+
+        return f32::clamp((value - self.min) / (self.max - self.min), 0.0, 1.0);
+    }
+}
+
+impl MacroMapping {
+    pub fn new() -> MacroMapping {
+        Self {
+            slots: std::array::from_fn(|_| MacroSlot::new()),
+            signature: MacroMapping::signature(&BTreeMap::new()),
+            ordered: Vec::with_capacity(ORDERED_CAPACITY)
+        }
+    }
+
+    pub fn get(&self, slot: usize) -> Option<&MacroSlot> {
+        return self.slots.get(slot).filter(|s| s.mapped);
+    }
+
+    // Remaps the slots if the registered parameters changed, and returns whether it did.
+    // Writes the assigned slot back into each parameter either way.
+    pub fn update(&mut self, parameters: &mut BTreeMap<String, Parameter>) -> bool {
+        let signature = MacroMapping::signature(parameters);
+        let remapped = signature != self.signature;
+
+        if remapped {
+            self.remap(parameters);
+            self.signature = signature;
+        }
+
+        for parameter in parameters.values_mut() {
+            parameter.mapped_slot = self.slots.iter().position(|s| s.mapped && s.name == parameter.name);
+        }
+
+        return remapped;
+    }
+
+    // Parameters that ask for a slot get it first. The rest fill the free slots in registration order.
+    fn remap(&mut self, parameters: &BTreeMap<String, Parameter>) {
+        self.ordered.clear();
+        self.ordered.extend(parameters.values().enumerate().map(|(index, p)| (p.order, index, p.slot)));
+        self.ordered.sort_unstable();
+
+        let mut assigned: [Option<usize>; MACRO_SLOT_COUNT] = [None; MACRO_SLOT_COUNT];
+
+        for (_order, index, slot) in self.ordered.iter() {
+            match *slot {
+                // Lua indexes start at 1
+                Some(slot) if slot >= 1 && slot <= MACRO_SLOT_COUNT && assigned[slot - 1].is_none() => {
+                    assigned[slot - 1] = Some(*index);
+                },
+                _ => ()
+            }
+        }
+
+        let mut next_slot = 0;
+        for (_order, index, _slot) in self.ordered.iter() {
+            if assigned.contains(&Some(*index)) { continue; }
+
+            while next_slot < MACRO_SLOT_COUNT && assigned[next_slot].is_some() {
+                next_slot += 1;
+            }
+            if next_slot >= MACRO_SLOT_COUNT { break; }
+
+            assigned[next_slot] = Some(*index);
+        }
+
+        for slot in self.slots.iter_mut() {
+            slot.mapped = false;
+        }
+        for (index, parameter) in parameters.values().enumerate() {
+            match assigned.iter().position(|a| *a == Some(index)) {
+                Some(slot) => self.slots[slot].set(parameter),
+                None => ()
+            }
+        }
+    }
+
+    fn signature(parameters: &BTreeMap<String, Parameter>) -> u64 {
+        let mut hasher = DefaultHasher::new();
+
+        for parameter in parameters.values() {
+            parameter.name.hash(&mut hasher);
+            parameter.order.hash(&mut hasher);
+            parameter.slot.hash(&mut hasher);
+            parameter.min.to_bits().hash(&mut hasher);
+            parameter.max.to_bits().hash(&mut hasher);
+            parameter.step_size.to_bits().hash(&mut hasher);
+        }
+
+        return hasher.finish();
+    }
+
+    // What the host shows for a slot.
+    pub fn format(&self, slot: usize, normalized: f32) -> String {
+        match self.get(slot) {
+            Some(s) => return format!("{name}: {value:.2}", name = s.name, value = s.to_value(normalized)),
+            None => return format!("Unmapped ({:.0}%)", normalized * 100.0)
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use std::{ fs, io, path::Path };
+use serde::{ Deserialize, Serialize };
+
+use super::{ library, sandbox::SandboxLevel };
+
+// Bumped when the globals and includes modules rely on change in incompatible ways.
+pub const API_VERSION: u32 = 1;
+pub const DEFAULT_MODULE_VERSION: &str = "0.1.0";
+
+// Optional module.toml in a workspace folder. Describes the module without running it.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct ModuleManifest {
+    pub name: String,
+    pub version: String,
+    pub authors: Vec<String>,
+    pub description: String,
+    pub tags: Vec<String>,
+    pub api_version: u32,
+    // Suggested sandbox level, "sandboxed" or "trusted". The user still has to trust the workspace.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sandbox: Option<String>,
+    pub entry: EntryFiles
+}
+
+// Which files hold each section, relative to the workspace folder.
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(default)]
+pub struct EntryFiles {
+    pub init: String,
+    pub reset: String,
+    pub trigger: String,
+    pub run: String,
+    pub interface: String
+}
+
+impl ModuleManifest {
+    pub fn new(name: String) -> ModuleManifest {
+        Self {
+            name: name,
+            version: String::from(DEFAULT_MODULE_VERSION),
+            authors: Vec::new(),
+            description: String::new(),
+            tags: Vec::new(),
+            api_version: API_VERSION,
+            sandbox: None,
+            entry: EntryFiles::default()
+        }
+    }
+
+    // None when the folder has no manifest.
+    pub fn read_from_folder(path: &str) -> Result<Option<ModuleManifest>, String> {
+        let file = format!("{path}/{file}", path = path, file = library::MANIFEST_PATH);
+        let contents = match fs::read_to_string(&file) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Couldn't read {}: {}", library::MANIFEST_PATH, e))
+        };
+
+        let manifest: ModuleManifest = match toml::from_str(&contents) {
+            Ok(m) => m,
+            Err(e) => return Err(format!("Couldn't parse {}: {}", library::MANIFEST_PATH, e))
+        };
+
+        if manifest.api_version > API_VERSION {
+            return Err(format!("{name} needs API version {required}, this version of the plugin supports {supported}.",
+                name = manifest.name, required = manifest.api_version, supported = API_VERSION));
+        }
+
+        match &manifest.sandbox {
+            Some(s) if SandboxLevel::from_name(s).is_none() => return Err(format!("Unknown sandbox level \"{}\" in {}.", s, library::MANIFEST_PATH)),
+            _ => ()
+        }
+
+        return Ok(Some(manifest));
+    }
+
+    pub fn write_to_folder(&self, path: &str) -> Result<(), String> {
+        let contents = match toml::to_string_pretty(self) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't write {}: {}", library::MANIFEST_PATH, e))
+        };
+
+        match fs::write(format!("{path}/{file}", path = path, file = library::MANIFEST_PATH), contents) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(format!("Couldn't write {}: {}", library::MANIFEST_PATH, e))
+        }
+    }
+
+    // The folder name stands in for a name until one is set.
+    pub fn for_folder(path: &str) -> ModuleManifest {
+        let name = Path::new(path).file_name().map_or(String::from("Untitled"), |n| n.to_string_lossy().to_string());
+
+        return ModuleManifest::new(name);
+    }
+
+    pub fn sandbox_level(&self) -> Option<SandboxLevel> {
+        return self.sandbox.as_deref().and_then(SandboxLevel::from_name);
+    }
+}
+
+impl Default for ModuleManifest {
+    fn default() -> Self {
+        ModuleManifest::new(String::new())
+    }
+}
+
+impl Default for EntryFiles {
+    fn default() -> Self {
+        Self {
+            init: String::from(library::INIT_PATH),
+            reset: String::from(library::RESET_PATH),
+            trigger: String::from(library::TRIGGER_PATH),
+            run: String::from(library::RUN_PATH),
+            interface: String::from(library::INTERFACE_PATH)
+        }
+    }
+}
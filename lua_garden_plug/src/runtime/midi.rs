@@ -0,0 +1,230 @@
+use mlua::prelude::*;
+use nih_plug::prelude::*;
+
+const LUA_TYPE_KEY: &str = "type";
+const LUA_OFFSET_KEY: &str = "offset";
+const LUA_SAMPLE_KEY: &str = "sample";
+const LUA_CHANNEL_KEY: &str = "channel";
+const LUA_NOTE_KEY: &str = "note";
+const LUA_VELOCITY_KEY: &str = "velocity";
+const LUA_CC_KEY: &str = "cc";
+const LUA_VALUE_KEY: &str = "value";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MidiEventKind {
+    NoteOn,
+    NoteOff,
+    PitchBend,
+    ControlChange
+}
+
+// A MIDI event as modules see it. Offset is the sample within the block, starting at 0.
+#[derive(Clone, Copy, Debug)]
+pub struct MidiEvent {
+    pub kind: MidiEventKind,
+    pub offset: usize,
+    pub channel: u8,
+    // Note for note events, controller for control changes.
+    pub number: u8,
+    // Velocity for note events, -1 to 1 for pitch bend and 0 to 1 for control changes.
+    pub value: f32
+}
+
+impl MidiEventKind {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            MidiEventKind::NoteOn => "note_on",
+            MidiEventKind::NoteOff => "note_off",
+            MidiEventKind::PitchBend => "pitch_bend",
+            MidiEventKind::ControlChange => "cc"
+        };
+    }
+}
+
+impl MidiEventKind {
+    pub fn from_name(name: &str) -> Option<MidiEventKind> {
+        return match name {
+            "note_on" => Some(MidiEventKind::NoteOn),
+            "note_off" => Some(MidiEventKind::NoteOff),
+            "pitch_bend" => Some(MidiEventKind::PitchBend),
+            "cc" => Some(MidiEventKind::ControlChange),
+            _ => None
+        };
+    }
+}
+
+impl MidiEvent {
+    // Returns None for events modules don't receive.
+    pub fn from_note_event<S>(event: &NoteEvent<S>) -> Option<MidiEvent> {
+        let (kind, channel, number, value) = match *event {
+            NoteEvent::NoteOn { channel, note, velocity, .. } => (MidiEventKind::NoteOn, channel, note, velocity),
+            NoteEvent::NoteOff { channel, note, velocity, .. } => (MidiEventKind::NoteOff, channel, note, velocity),
+            NoteEvent::MidiPitchBend { channel, value, .. } => (MidiEventKind::PitchBend, channel, 0, value * 2.0 - 1.0),
+            NoteEvent::MidiCC { channel, cc, value, .. } => (MidiEventKind::ControlChange, channel, cc, value),
+            _ => return None
+        };
+
+        return Some(Self {
+            kind: kind,
+            offset: event.timing() as usize,
+            channel: channel,
+            number: number,
+            value: value
+        });
+    }
+
+    pub fn to_note_event<S>(&self) -> NoteEvent<S> {
+        let timing = self.offset as u32;
+
+        return match self.kind {
+            MidiEventKind::NoteOn => NoteEvent::NoteOn { timing: timing, voice_id: None, channel: self.channel, note: self.number, velocity: self.value },
+            MidiEventKind::NoteOff => NoteEvent::NoteOff { timing: timing, voice_id: None, channel: self.channel, note: self.number, velocity: self.value },
+            MidiEventKind::PitchBend => NoteEvent::MidiPitchBend { timing: timing, channel: self.channel, value: (self.value + 1.0) / 2.0 },
+            MidiEventKind::ControlChange => NoteEvent::MidiCC { timing: timing, channel: self.channel, cc: self.number, value: self.value }
+        };
+    }
+
+    // Reads an event queued by a module. Values are clamped, and the sample is kept within the buffer.
+    pub fn from_lua(table: &LuaTable, buffer_size: usize) -> LuaResult<MidiEvent> {
+        let name: String = table.get(LUA_TYPE_KEY)?;
+        let kind = match MidiEventKind::from_name(&name) {
+            Some(k) => k,
+            None => return Err(LuaError::runtime(format!("Unknown MIDI event type \"{}\".", name)))
+        };
+
+        let sample: i64 = table.get(LUA_SAMPLE_KEY)?;
+        let channel: i64 = table.get(LUA_CHANNEL_KEY)?;
+        let (number, value): (i64, f32) = match kind {
+            MidiEventKind::NoteOn | MidiEventKind::NoteOff => (table.get(LUA_NOTE_KEY)?, table.get(LUA_VELOCITY_KEY)?),
+            MidiEventKind::PitchBend => (0, table.get(LUA_VALUE_KEY)?),
+            MidiEventKind::ControlChange => (table.get(LUA_CC_KEY)?, table.get(LUA_VALUE_KEY)?)
+        };
+        let min_value = if kind == MidiEventKind::PitchBend { -1.0 } else { 0.0 };
+
+        return Ok(Self {
+            kind: kind,
+            // Lua indexes start at 1
+            offset: i64::clamp(sample - 1, 0, buffer_size.saturating_sub(1) as i64) as usize,
+            channel: i64::clamp(channel - 1, 0, 15) as u8,
+            number: i64::clamp(number, 0, 127) as u8,
+            value: f32::clamp(value, min_value, 1.0)
+        });
+    }
+
+    // Lua indexes start at 1, so channels and samples do too.
+    pub fn to_lua(&self, lua: &Lua) -> LuaResult<LuaTable> {
+        let table = lua.create_table()?;
+
+        table.set(LUA_TYPE_KEY, self.kind.name())?;
+        table.set(LUA_OFFSET_KEY, self.offset)?;
+        table.set(LUA_SAMPLE_KEY, self.offset + 1)?;
+        table.set(LUA_CHANNEL_KEY, self.channel + 1)?;
+
+        match self.kind {
+            MidiEventKind::NoteOn | MidiEventKind::NoteOff => {
+                table.set(LUA_NOTE_KEY, self.number)?;
+                table.set(LUA_VELOCITY_KEY, self.value)?;
+            },
+            MidiEventKind::PitchBend => {
+                table.set(LUA_VALUE_KEY, self.value)?;
+            },
+            MidiEventKind::ControlChange => {
+                table.set(LUA_CC_KEY, self.number)?;
+                table.set(LUA_VALUE_KEY, self.value)?;
+            }
+        }
+
+        return Ok(table);
+    }
+}
+
+// Notes that were turned on and not off yet, one bit per note and channel. Fixed size, so tracking doesn't allocate.
+#[derive(Clone, Copy)]
+pub struct ActiveNotes {
+    channels: [u128; 16]
+}
+
+impl ActiveNotes {
+    pub const fn new() -> ActiveNotes {
+        Self {
+            channels: [0; 16]
+        }
+    }
+
+    // A note on with velocity 0 counts as a note off.
+    pub fn track(&mut self, event: &MidiEvent) {
+        let note = 1u128 << (event.number & 127);
+        let channel = &mut self.channels[(event.channel & 15) as usize];
+
+        match event.kind {
+            MidiEventKind::NoteOn if event.value > 0.0 => *channel |= note,
+            MidiEventKind::NoteOn | MidiEventKind::NoteOff => *channel &= !note,
+            _ => ()
+        }
+    }
+
+    // Moves other's notes into these.
+    pub fn merge(&mut self, other: &mut ActiveNotes) {
+        for (channel, other_channel) in self.channels.iter_mut().zip(other.channels.iter()) {
+            *channel |= *other_channel;
+        }
+        other.clear();
+    }
+
+    pub fn clear(&mut self) {
+        self.channels = [0; 16];
+    }
+
+    // A note off at the start of the block for every active note.
+    pub fn note_offs(&self) -> impl Iterator<Item = MidiEvent> + '_ {
+        return self.channels.iter().enumerate().flat_map(|(channel, notes)| {
+            (0..128u8).filter(move |note| (notes >> note) & 1 == 1).map(move |note| MidiEvent {
+                kind: MidiEventKind::NoteOff,
+                offset: 0,
+                channel: channel as u8,
+                number: note,
+                value: 0.0
+            })
+        });
+    }
+}
+
+// Stable insertion sort by offset, so events leave in time order without allocating on the audio thread.
+pub fn sort_by_offset(events: &mut [MidiEvent]) {
+    for i in 1..events.len() {
+        let mut j = i;
+        while j > 0 && events[j - 1].offset > events[j].offset {
+            events.swap(j - 1, j);
+            j -= 1;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note(kind: MidiEventKind, channel: u8, number: u8, value: f32) -> MidiEvent {
+        return MidiEvent { kind: kind, offset: 0, channel: channel, number: number, value: value };
+    }
+
+    #[test]
+    fn active_notes_release_what_is_still_on() {
+        let mut active = ActiveNotes::new();
+        active.track(&note(MidiEventKind::NoteOn, 0, 60, 1.0));
+        active.track(&note(MidiEventKind::NoteOn, 0, 64, 1.0));
+        active.track(&note(MidiEventKind::NoteOn, 9, 127, 0.5));
+        active.track(&note(MidiEventKind::NoteOff, 0, 60, 0.0));
+        active.track(&note(MidiEventKind::NoteOn, 0, 67, 1.0));
+        active.track(&note(MidiEventKind::NoteOn, 0, 67, 0.0));
+
+        let mut released = ActiveNotes::new();
+        released.merge(&mut active);
+        let note_offs: Vec<(u8, u8)> = released.note_offs().map(|e| (e.channel, e.number)).collect();
+
+        assert_eq!(note_offs, vec![(0, 64), (9, 127)]);
+        assert!(released.note_offs().all(|e| e.kind == MidiEventKind::NoteOff && e.offset == 0));
+        assert_eq!(active.note_offs().count(), 0);
+    }
+}
@@ -1,15 +1,40 @@
 pub mod module;
+pub mod audio_buffer;
 pub mod workspace;
 pub mod utils;
 pub mod library;
 pub mod module_content;
 pub mod runtime_data;
 pub mod parameter;
-
-use crate::console::ConsoleSender;
+pub mod watchdog;
+pub mod sandbox;
+pub mod crossfade;
+pub mod bypass;
+pub mod midi;
+pub mod layout;
+pub mod macros;
+pub mod module_state;
+pub mod preset;
+pub mod exchange;
+pub mod manifest;
+pub mod require;
+pub mod user_library;
+pub mod workspace_browser;
+
+use crate::{ consts, console::ConsoleSender };
 use module::RuntimeModule;
 use module_content::ModuleContent;
 use utils::{ Timer, RMS };
+use watchdog::Watchdog;
+use sandbox::SandboxLevel;
+use crossfade::Crossfade;
+use bypass::BypassFade;
+use midi::{ ActiveNotes, MidiEvent };
+use layout::ChannelLayout;
+use user_library::UserLibrary;
+use manifest::EntryFiles;
+use library::LuaFiles;
+use runtime_data::RuntimeState;
 use mlua::prelude::*;
 use nih_plug::prelude::*;
 
@@ -21,14 +46,39 @@ pub struct Runtime {
     pub description: String,
 
     module: Option<RuntimeModule>,
+    module_running: bool,
+    crossfade: Crossfade,
+    crossfade_ms: f32,
 
     sample_rate : f32,
     buffer_size : usize,
     channels : usize,
+    layout: ChannelLayout,
 
     run_time_rms: RMS,
     input_noise: bool,
-    clip: bool
+    clip: bool,
+
+    watchdog: Watchdog,
+    watchdog_budget: f32,
+
+    memory_limit: usize,
+    memory_used: usize,
+    memory_peak: usize,
+    memory_exceeded: bool,
+    // Times used memory went down between runs. Not a count of collections, the collector works in steps.
+    memory_drops: u32,
+    gc_freed: usize,
+    gc_running: bool,
+
+    bypass_fade: BypassFade,
+    last_error: Option<String>,
+
+    midi_output: Vec<MidiEvent>,
+    // Notes left on by modules that stopped or were replaced.
+    released_notes: ActiveNotes,
+
+    user_library: Option<UserLibrary>
 }
 
 impl Runtime {
@@ -41,29 +91,84 @@ impl Runtime {
             description: String::new(),
 
             module: None,
+            module_running: false,
+            crossfade: Crossfade::new(),
+            crossfade_ms: crossfade::DEFAULT_CROSSFADE_MS,
 
             sample_rate: 0.0,
             buffer_size: 0,
             channels: 0,
+            layout: ChannelLayout::stereo(),
 
             run_time_rms: RMS::new(),
             input_noise: false,
-            clip: true
+            clip: true,
+
+            watchdog: Watchdog::new(),
+            watchdog_budget: watchdog::DEFAULT_BUDGET,
+
+            memory_limit: module::DEFAULT_MEMORY_LIMIT,
+            memory_used: 0,
+            memory_peak: 0,
+            memory_exceeded: false,
+            memory_drops: 0,
+            gc_freed: 0,
+            gc_running: false,
+
+            bypass_fade: BypassFade::new(),
+            last_error: None,
+
+            midi_output: Vec::with_capacity(consts::MAX_MIDI_EVENTS_PER_BLOCK),
+            released_notes: ActiveNotes::new(),
+
+            user_library: None
         };
 
         return runtime;
     }
 
-    pub fn load_new_module(&mut self, content: ModuleContent) {
-        let module = RuntimeModule::new(content, self.sample_rate);
-        
-        self.load_module(Some(module));
+    // Files are the workspace's lua files, read by the interface so loading doesn't touch the disk.
+    pub fn load_new_module(&mut self, content: ModuleContent, sandbox: SandboxLevel, path: Option<&str>, files: Option<&LuaFiles>, entry: &EntryFiles) -> bool {
+        // Workspaces can require their other lua files, any module can require from the user library
+        let mut require_roots = Vec::new();
+        match (path, files) {
+            (Some(p), Some(f)) => require_roots.push((String::from(p), f.clone())),
+            _ => ()
+        }
+        match &self.user_library {
+            Some(l) => require_roots.push((l.path.clone(), l.files.clone())),
+            None => ()
+        }
+
+        match RuntimeModule::new(content, self.sample_rate, &self.layout, &self.watchdog, self.memory_limit, sandbox, path, entry, require_roots) {
+            Ok(module) => {
+                // Keep a running module around to fade out, or to fall back to if the new one fails.
+                let previous = if self.module_running { self.module.take() } else { None };
+
+                self.load_module(Some(module));
+
+                match previous {
+                    Some(p) => self.crossfade.stash(p),
+                    None => ()
+                }
+                return true;
+            },
+            Err(e) => {
+                self.memory_exceeded = module::is_memory_error(&e);
+                let message = format!("Failed to load module: {e}");
+                self.log(message.clone());
+                self.last_error = Some(message);
+                return false;
+            }
+        }
     }
 
     pub fn load_module(&mut self, module: Option<RuntimeModule>) {
+        self.release_module_notes();
+
         match module {
             Some(m) => {
-                self.log(format!("Loading module... ({})\n", m.hash));
+                self.log(format!("Loading module... ({hash}, {sandbox})\n", hash = m.hash, sandbox = m.sandbox.name()));
                 self.module = Some(m);
             }
             None => {
@@ -71,8 +176,42 @@ impl Runtime {
                 self.module = None;
             }
         }
+
+        self.module_running = false;
+        self.crossfade.clear();
+
+        self.memory_exceeded = false;
+        self.reset_memory_usage();
     }
     
+    // Fades from the previous module to the current one, if there is a previous module.
+    pub fn begin_crossfade(&mut self) {
+        let samples = (self.crossfade_ms / 1000.0 * self.sample_rate) as usize;
+        self.crossfade.begin(samples);
+    }
+
+    // Falls back to the previous module after a failed reload. Returns whether a running module remains.
+    pub fn restore_previous_module(&mut self) -> bool {
+        match self.crossfade.take() {
+            Some(previous) => {
+                self.log(format!("Falling back to previous module ({}).", previous.hash));
+                self.release_module_notes();
+                self.module = Some(previous);
+                self.module_running = true;
+                self.memory_exceeded = false;
+                self.reset_memory_usage();
+                self.apply_metadata();
+            },
+            None => {
+                if self.module_running {
+                    self.log(format!("Keeping the running module."));
+                }
+            }
+        }
+
+        return self.module_running;
+    }
+
     pub fn init(&mut self, sample_rate: Option<f32>) -> bool {        
         match sample_rate {
             Some(rate) => self.sample_rate = rate,
@@ -80,17 +219,25 @@ impl Runtime {
         }
 
         let execute_timer = Timer::new();
+        self.watchdog.arm(watchdog::INIT_BUDGET_MS);
         let init_result = self.initialize_lua();
+        self.watchdog.disarm();
         let execute_time = execute_timer.elapsed_ms();
 
+        match &self.user_library {
+            Some(l) => self.log(l.describe()),
+            None => ()
+        }
+
         match init_result {
             Ok(_r) => {
                 self.run_time_rms.set(execute_time);
+                self.last_error = None;
                 self.log(format!("Initialization took {:.2}ms.", execute_time));
                 return true;
             },
             Err(e) => {
-                self.log(format!("Failed to initialize: {e}"));
+                self.fail("initialize", &e, execute_time, watchdog::INIT_BUDGET_MS);
                 return false;
             }
         }
@@ -98,52 +245,69 @@ impl Runtime {
 
     pub fn reset(&mut self) -> bool {
         let execute_timer = Timer::new();
+        self.watchdog.arm(watchdog::INIT_BUDGET_MS);
         let reset_result = self.reset_lua();
+        self.watchdog.disarm();
+        let execute_time = execute_timer.elapsed_ms();
 
         match reset_result {
             Ok(_r) => {
-                self.log(format!("Reset in {:.2}ms.", execute_timer.elapsed_ms()));
+                self.log(format!("Reset in {:.2}ms.", execute_time));
+                self.apply_metadata();
                 return true;
             },
             Err(e) => {
-                self.log(format!("Failed to reset: {e}"));
+                self.fail("reset", &e, execute_time, watchdog::INIT_BUDGET_MS);
                 return  false;
             }
         }
     }
 
-    pub fn trigger(&mut self) -> bool {
+    pub fn trigger(&mut self, offset: usize) -> bool {
         let execute_timer = Timer::new();
-        let trigger_result = self.trigger_lua();
+        let budget_ms = self.get_budget_ms(self.buffer_size);
+        self.watchdog.arm(budget_ms);
+        let trigger_result = self.trigger_lua(offset);
+        self.watchdog.disarm();
+        let execute_time = execute_timer.elapsed_ms();
 
         match trigger_result {
             Ok(_r) => {
-                self.log(format!("Triggered in {:.2}ms.", execute_timer.elapsed_ms()));
                 return true;
             },
             Err(e) => {
-                self.log(format!("Failed to trigger: {e}"));
+                self.fail_audio("trigger", &e, execute_time, budget_ms);
                 return  false;
             }
         }
     }
 
-    pub fn run(&mut self, buffer : &mut Buffer) -> bool {
+    pub fn run<S: AsRef<[f32]>>(&mut self, buffer : &mut Buffer, sidechain: &[S], midi_events: &[MidiEvent], transport: &Transport) -> bool {
         let execute_timer = Timer::new();
-        let run_result = self.run_lua(buffer);
+        let budget_ms = self.get_budget_ms(buffer.samples());
+        let run_result = self.run_lua(buffer, sidechain, midi_events, transport, budget_ms);
+        let execute_time = execute_timer.elapsed_ms();
 
         match run_result {
             Ok(_r) => {
-                self.run_time_rms.process( execute_timer.elapsed_ms(), self.sample_rate);
+                self.run_time_rms.process(execute_time, self.sample_rate);
+                self.update_memory_usage();
+                self.bypass_fade.hold(buffer.as_slice());
+                self.module_running = true;
                 return true;
             },
             Err(e) => {
-                self.log(format!("Failed to run: {e}"));
+                self.fail_audio("run", &e, execute_time, budget_ms);
                 return  false;
             }
         }
     }
 
+    // Passes dry audio through, fading from the module's last output.
+    pub fn bypass(&mut self, buffer: &mut Buffer) {
+        self.bypass_fade.process(buffer.as_slice());
+    }
+
     pub fn get_sample_rate(&self) -> f32 {
         return self.sample_rate;
     }
@@ -156,6 +320,30 @@ impl Runtime {
         return self.channels;
     }
 
+    // The user library as scanned by the interface. Takes effect the next time a module is loaded.
+    pub fn set_user_library(&mut self, user_library: Option<UserLibrary>) {
+        self.user_library = user_library;
+    }
+
+    pub fn get_layout(&self) -> &ChannelLayout {
+        return &self.layout;
+    }
+
+    pub fn set_layout(&mut self, layout: ChannelLayout) {
+        if self.layout == layout { return; }
+
+        self.layout = layout;
+        match &mut self.module {
+            Some(module) => {
+                match module.set_layout(&layout) {
+                    Ok(()) => (),
+                    Err(e) => self.log(format!("Failed to set channel layout: {e}"))
+                }
+            },
+            None => ()
+        }
+    }
+
     pub fn get_run_ms(&self) -> f32 {
         return self.run_time_rms.get();
     }
@@ -168,6 +356,195 @@ impl Runtime {
         self.input_noise = input_noise;
     }
 
+    pub fn set_crossfade_ms(&mut self, crossfade_ms: f32) {
+        self.crossfade_ms = f32::clamp(crossfade_ms, crossfade::MIN_CROSSFADE_MS, crossfade::MAX_CROSSFADE_MS);
+    }
+
+    pub fn set_watchdog_budget(&mut self, watchdog_budget: f32) {
+        self.watchdog_budget = f32::clamp(watchdog_budget, watchdog::MIN_BUDGET, watchdog::MAX_BUDGET);
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) {
+        if self.memory_limit == memory_limit { return; }
+
+        self.memory_limit = memory_limit;
+        match &mut self.module {
+            Some(module) => {
+                match module.set_memory_limit(memory_limit) {
+                    Ok(()) => (),
+                    Err(e) => self.log(format!("Failed to set memory limit: {e}"))
+                }
+            },
+            None => ()
+        }
+    }
+
+    pub fn get_memory_limit(&self) -> usize {
+        return self.memory_limit;
+    }
+
+    pub fn get_memory_used(&self) -> usize {
+        return self.memory_used;
+    }
+
+    pub fn get_memory_peak(&self) -> usize {
+        return self.memory_peak;
+    }
+
+    // How often used memory went down between runs, by how much in total, and whether the collector is running.
+    pub fn get_gc_stats(&self) -> (u32, usize, bool) {
+        return (self.memory_drops, self.gc_freed, self.gc_running);
+    }
+
+    pub fn get_sandbox(&self) -> Option<SandboxLevel> {
+        return self.module.as_ref().map(|m| m.sandbox);
+    }
+
+    pub fn has_module(&self) -> bool {
+        return self.module.is_some();
+    }
+
+    pub fn exceeded_memory(&self) -> bool {
+        return self.memory_exceeded;
+    }
+
+    pub fn exceeded_budget(&self) -> bool {
+        return self.watchdog.tripped();
+    }
+
+    // The state to go to after the module failed.
+    pub fn failed_state(&self) -> RuntimeState {
+        if self.memory_exceeded || self.module.is_none() {
+            return RuntimeState::Offline;
+        }
+
+        return RuntimeState::Bypass;
+    }
+
+    pub fn get_last_error(&self) -> Option<&str> {
+        return self.last_error.as_deref();
+    }
+
+    pub fn set_parameter_value(&mut self, name: &str, value: f32) {
+        match &mut self.module {
+            Some(module) => {
+                match module.queue_parameter_value(name, value) {
+                    Ok(()) => (),
+                    Err(e) => self.log(format!("Failed to set parameter \"{name}\": {e}"))
+                }
+            },
+            None => ()
+        }
+    }
+
+    // Reading the module's tables can still run finalizers, so it gets the same budget as a run.
+    // Exceeding it bypasses the module like a failed run.
+    pub fn read_module<T>(&mut self, read: impl FnOnce(&mut RuntimeModule) -> LuaResult<T>) -> Option<LuaResult<T>> {
+        let execute_timer = Timer::new();
+        let budget_ms = self.get_budget_ms(self.buffer_size);
+        self.watchdog.arm(budget_ms);
+        let read_result = self.module.as_mut().map(read);
+        self.watchdog.disarm();
+        let execute_time = execute_timer.elapsed_ms();
+
+        match &read_result {
+            Some(Err(e)) if self.watchdog.tripped() => self.fail_audio("read parameters", e, execute_time, budget_ms),
+            _ => ()
+        }
+
+        return read_result;
+    }
+
+    // MIDI the module sent during the last run.
+    pub fn get_midi_output(&self) -> &[MidiEvent] {
+        return &self.midi_output;
+    }
+
+    // Notes left on by modules that were bypassed, cleared or replaced since the last call. Send their note offs before the module's MIDI.
+    pub fn take_released_notes(&mut self) -> ActiveNotes {
+        self.crossfade.release_notes(&mut self.released_notes);
+
+        let mut released = ActiveNotes::new();
+        released.merge(&mut self.released_notes);
+        return released;
+    }
+
+    // Shows the module's metadata. Only once it initialized and reset, so a failed reload keeps showing the module that runs.
+    fn apply_metadata(&mut self) {
+        match &self.module {
+            Some(module) => {
+                self.name = module.name.clone();
+                self.author = module.author.clone();
+                self.description = module.description.clone();
+            },
+            None => ()
+        }
+    }
+
+    fn release_module_notes(&mut self) {
+        match &mut self.module {
+            Some(module) => module.release_notes(&mut self.released_notes),
+            None => ()
+        }
+    }
+
+    fn update_memory_usage(&mut self) {
+        let previous = self.memory_used;
+        (self.memory_used, self.gc_running) = match &self.module {
+            Some(module) => (module.used_memory(), module.gc_running()),
+            None => (0, false)
+        };
+        self.memory_peak = usize::max(self.memory_peak, self.memory_used);
+
+        if self.memory_used < previous {
+            self.memory_drops = self.memory_drops.wrapping_add(1);
+            self.gc_freed = self.gc_freed.saturating_add(previous - self.memory_used);
+        }
+    }
+
+    // Starts the statistics over for a different module.
+    fn reset_memory_usage(&mut self) {
+        self.memory_used = 0;
+        self.memory_peak = 0;
+        self.memory_drops = 0;
+        self.gc_freed = 0;
+        self.update_memory_usage();
+    }
+
+    fn fail(&mut self, action: &str, error: &LuaError, execute_time: f32, budget_ms: f32) {
+        self.memory_exceeded = module::is_memory_error(error);
+
+        let message = if self.watchdog.tripped() {
+            format!("Module exceeded budget while trying to {action} ({:.2}ms of {:.2}ms). Bypassing.", execute_time, budget_ms)
+        } else if self.memory_exceeded {
+            format!("Module exceeded memory limit while trying to {action} ({used:.2}MB of {limit:.2}MB). Going offline.",
+                used = utils::bytes_to_mb(self.memory_used),
+                limit = utils::bytes_to_mb(self.memory_limit))
+        } else {
+            format!("Failed to {action}: {error}")
+        };
+
+        self.log(message.clone());
+        self.last_error = Some(message);
+    }
+
+    // Failing while processing audio stops the module and fades to the dry signal.
+    fn fail_audio(&mut self, action: &str, error: &LuaError, execute_time: f32, budget_ms: f32) {
+        self.module_running = false;
+        self.release_module_notes();
+        self.crossfade.clear();
+        self.bypass_fade.begin((bypass::BYPASS_FADE_MS / 1000.0 * self.sample_rate) as usize);
+        self.fail(action, error, execute_time, budget_ms);
+    }
+
+    fn get_budget_ms(&self, buffer_size: usize) -> f32 {
+        if self.sample_rate <= 0.0 || buffer_size == 0 {
+            return watchdog::INIT_BUDGET_MS;
+        }
+
+        return buffer_size as f32 / self.sample_rate * 1000.0 * self.watchdog_budget;
+    }
+
     fn initialize_lua(&mut self) -> LuaResult<()> {
         self.log(format!("Setting up Lua state..."));
 
@@ -181,10 +558,6 @@ impl Runtime {
                             name = r.0, 
                             authors = r.1,
                             about = r.2));
-
-                        self.name = r.0.clone();
-                        self.author = r.1.clone();
-                        self.description = r.2.clone();
                     },
                     Err(_e) => { 
                         init_result?; 
@@ -206,23 +579,64 @@ impl Runtime {
         Ok(())
     }
 
-    fn trigger_lua(&mut self) -> LuaResult<()> {
+    fn trigger_lua(&mut self, offset: usize) -> LuaResult<()> {
         match &mut self.module {
-            Some(module) => module.trigger()?,
+            Some(module) => module.trigger(offset)?,
             None => self.log(format!("No module loaded."))
         }
         
         Ok(())
     }
 
-    fn run_lua(&mut self, buffer : &mut Buffer) -> LuaResult<()> {
+    // Both modules of a crossfade get their own budget, so a reload can't push the incoming module over its budget.
+    fn run_lua<S: AsRef<[f32]>>(&mut self, buffer : &mut Buffer, sidechain: &[S], midi_events: &[MidiEvent], transport: &Transport, budget_ms: f32) -> LuaResult<()> {
         self.channels = buffer.channels();
         self.buffer_size = buffer.samples();
+        self.midi_output.clear();
 
-        match &mut self.module {
+        let samples = buffer.as_slice();
+
+        // The outgoing module gets a copy of the input first. If it fails, only the crossfade is cut short.
+        self.watchdog.arm(budget_ms);
+        let crossfade_result = self.crossfade.run(samples, sidechain, midi_events, transport, self.input_noise, self.clip);
+        let crossfade_midi_result = match &crossfade_result {
+            Some(Ok(_logs)) => Some(self.crossfade.process_midi_output(&mut self.midi_output, self.buffer_size, consts::MAX_MIDI_EVENTS_PER_BLOCK)),
+            _ => None
+        };
+        self.watchdog.disarm();
+
+        match crossfade_result {
+            Some(Ok(logs)) => {
+                for log in logs {
+                    self.log(log);
+                }
+
+                match crossfade_midi_result {
+                    Some(Err(e)) => self.log(format!("Previous module failed to send MIDI during crossfade: {e}")),
+                    _ => ()
+                }
+            },
+            Some(Err(e)) => {
+                self.log(format!("Previous module failed during crossfade: {e}"));
+                self.crossfade.clear();
+            },
+            None => ()
+        }
+
+        self.watchdog.arm(budget_ms);
+        let module_result = match &mut self.module {
             Some(module) => {
-                let logs = module.run(buffer, self.input_noise, self.clip)?;
-            
+                match module.run(samples, sidechain, midi_events, transport, self.input_noise, self.clip) {
+                    Ok(logs) => module.process_midi_output(&mut self.midi_output, self.buffer_size, consts::MAX_MIDI_EVENTS_PER_BLOCK).map(|()| Some(logs)),
+                    Err(e) => Err(e)
+                }
+            }
+            None => Ok(None)
+        };
+        self.watchdog.disarm();
+
+        match module_result? {
+            Some(logs) => {
                 for log in logs {
                     self.log(log);
                 }
@@ -230,10 +644,15 @@ impl Runtime {
             None => self.log(format!("No module loaded."))
         }
 
+        self.crossfade.mix(samples);
+
+        // Scripts may queue events in any order, hosts expect them in time order
+        midi::sort_by_offset(&mut self.midi_output);
+
         Ok(())
     }
 
-    fn log(&self, message : String) {
+    pub fn log(&self, message : String) {
         match &self.console {
             Some(c) => {
                 c.log(message);
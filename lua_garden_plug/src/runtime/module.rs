@@ -1,64 +1,145 @@
 use std::collections::BTreeMap;
 
 use mlua::prelude::*;
-use nih_plug::prelude::*;
+use nih_plug::prelude::Transport;
 use crate::runtime::module_content::ModuleContent;
 
-use super::{library, parameter::Parameter, utils};
+use super::{audio_buffer::LuaBuffer, layout::ChannelLayout, library::{ self, LuaFiles }, manifest::EntryFiles, midi::{ ActiveNotes, MidiEvent }, parameter::Parameter, require, sandbox::SandboxLevel, watchdog::Watchdog};
 
-pub const LUA_BUFFERS_KEY: &str = "BUFFER_RAW";
+pub const LUA_BUFFER_KEY: &str = "BUFFER";
+pub const LUA_SIDECHAIN_KEY: &str = "SIDECHAIN";
 pub const LUA_SAMPLE_RATE_KEY: &str = "SAMPLE_RATE";
 pub const LUA_CHANNELS_KEY: &str = "CHANNELS";
+pub const LUA_INPUT_CHANNELS_KEY: &str = "INPUT_CHANNELS";
+pub const LUA_CHANNEL_NAMES_KEY: &str = "CHANNEL_NAMES";
+pub const LUA_LAYOUT_KEY: &str = "LAYOUT";
 pub const LUA_BUFFER_SIZE_KEY: &str = "BUFFER_SIZE";
 pub const LUA_INPUT_NOISE_KEY: &str = "INPUT_NOISE";
+pub const LUA_TRIGGER_SAMPLE_KEY: &str = "TRIGGER_SAMPLE";
+pub const LUA_TRIGGER_OFFSET_KEY: &str = "TRIGGER_OFFSET";
+pub const LUA_MIDI_EVENTS_KEY: &str = "MIDI_EVENTS";
+pub const LUA_MIDI_OUT_KEY: &str = "MIDI_OUT";
+pub const LUA_MIDI_OUT_COUNT_KEY: &str = "MIDI_OUT_COUNT";
+pub const LUA_TEMPO_KEY: &str = "TEMPO";
+pub const LUA_TIME_SIG_KEY: &str = "TIME_SIG";
+pub const LUA_TIME_SIG_NUMERATOR_KEY: &str = "numerator";
+pub const LUA_TIME_SIG_DENOMINATOR_KEY: &str = "denominator";
+pub const LUA_PLAYING_KEY: &str = "PLAYING";
+pub const LUA_POS_SAMPLES_KEY: &str = "POS_SAMPLES";
+pub const LUA_POS_BEATS_KEY: &str = "POS_BEATS";
+pub const LUA_BAR_START_KEY: &str = "BAR_START";
 pub const LUA_NAME_KEY: &str = "MODULE_NAME";
 pub const LUA_AUTHORS_KEY: &str = "MODULE_AUTHORS";
 pub const LUA_ABOUT_KEY: &str = "MODULE_ABOUT";
 pub const LUA_LOGS_KEY: &str = "LOGS";
 pub const LUA_PARAMETERS_KEY: &str = "PARAMETERS";
 pub const LUA_PARAMETER_VALUE_UPDATES_KEY: &str = "PARAMETER_VALUE_UPDATES";
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+pub const MIN_MEMORY_LIMIT: usize = 1024 * 1024;
+pub const MAX_MEMORY_LIMIT: usize = 1024 * 1024 * 1024;
 const UNKNOWN: &str = "???";
 
 pub struct RuntimeModule {
     pub hash: String,
+    pub sandbox: SandboxLevel,
+
+    // Read from the module's globals during init.
+    pub name: String,
+    pub author: String,
+    pub description: String,
     
     lua: Lua,
-    lua_buffers: LuaTable,
-    channels: usize,
+    lua_buffer: LuaAnyUserData,
+    lua_sidechain: LuaAnyUserData,
+    has_midi_events: bool,
+    active_notes: ActiveNotes,
+    time_sig: LuaTable,
+
+    includes: Vec<LuaFunction>,
+    init_section: ModuleSection,
+    reset_section: ModuleSection,
+    trigger_section: ModuleSection,
+    run_section: ModuleSection
+}
 
-    content: ModuleContent
+// A section of a module, compiled as separate chunks so errors point at the right file and line.
+struct ModuleSection {
+    header: LuaFunction,
+    content: LuaFunction,
+    footer: LuaFunction
 }
 
 impl RuntimeModule {
-    pub fn new(content: ModuleContent, sample_rate : f32) -> RuntimeModule {
-        let lua = Lua::new();
-        let lua_buffers = lua.create_table().expect("Couldn't create buffers.");
+    // Path is the workspace folder the content was read from, if any. It's used with the entry files to name chunks.
+    // Require roots are folders with the lua files modules may require from, already read.
+    pub fn new(content: ModuleContent, sample_rate : f32, layout: &ChannelLayout, watchdog: &Watchdog, memory_limit: usize, sandbox: SandboxLevel, path: Option<&str>, entry: &EntryFiles, require_roots: Vec<(String, LuaFiles)>) -> LuaResult<RuntimeModule> {
+        let lua = sandbox.create_lua()?;
+        lua.set_memory_limit(memory_limit)?;
+        watchdog.install(&lua)?;
+        require::install(&lua, require_roots, sandbox)?;
+
+        let lua_buffer = lua.create_userdata(LuaBuffer::new())?;
+        let lua_sidechain = lua.create_userdata(LuaBuffer::new_read_only())?;
+        let time_sig = lua.create_table()?;
+
+        lua.globals().set(LUA_BUFFER_KEY, &lua_buffer)?;
+        lua.globals().set(LUA_SIDECHAIN_KEY, &lua_sidechain)?;
+        lua.globals().set(LUA_SAMPLE_RATE_KEY, sample_rate)?;
+        RuntimeModule::apply_layout(&lua, layout)?;
+
+        // Compile everything once, so the audio thread only has to call it.
+        let mut includes = Vec::new();
+        for include in library::INTERNAL_INCLUDES {
+            includes.push(RuntimeModule::compile(&lua, include.0, &library::internal_chunk_name(include.1))?);
+        }
 
-        let mut module = Self {
-            hash: String::new(),
+        let init_section = ModuleSection::compile(&lua, 
+            (library::INIT_HEADER, library::INIT_HEADER_PATH), 
+            (content.init.as_str(), library::chunk_name(path, &entry.init).as_str()), 
+            (library::INIT_FOOTER, library::INIT_FOOTER_PATH))?;
+        let reset_section = ModuleSection::compile(&lua, 
+            (library::RESET_HEADER, library::RESET_HEADER_PATH), 
+            (content.reset.as_str(), library::chunk_name(path, &entry.reset).as_str()), 
+            (library::RESET_FOOTER, library::RESET_FOOTER_PATH))?;
+        let trigger_section = ModuleSection::compile(&lua, 
+            (library::TRIGGER_HEADER, library::TRIGGER_HEADER_PATH), 
+            (content.trigger.as_str(), library::chunk_name(path, &entry.trigger).as_str()), 
+            (library::TRIGGER_FOOTER, library::TRIGGER_FOOTER_PATH))?;
+        let run_section = ModuleSection::compile(&lua, 
+            (library::RUN_HEADER, library::RUN_HEADER_PATH), 
+            (content.run.as_str(), library::chunk_name(path, &entry.run).as_str()), 
+            (library::RUN_FOOTER, library::RUN_FOOTER_PATH))?;
+
+        let module = Self {
+            hash: format!("{:x}", content.generate_hash()),
+            sandbox: sandbox,
+
+            name: String::from(UNKNOWN),
+            author: String::from(UNKNOWN),
+            description: String::from(UNKNOWN),
 
             lua: lua,
-            lua_buffers: lua_buffers,
-            channels: 0,
-
-            content: content
+            lua_buffer: lua_buffer,
+            lua_sidechain: lua_sidechain,
+            has_midi_events: false,
+            active_notes: ActiveNotes::new(),
+            time_sig: time_sig,
+
+            includes: includes,
+            init_section: init_section,
+            reset_section: reset_section,
+            trigger_section: trigger_section,
+            run_section: run_section
         };
 
-        module.lua.globals().set(LUA_BUFFERS_KEY, &module.lua_buffers).expect("Couldn't set global.");
-        module.lua.globals().set(LUA_SAMPLE_RATE_KEY, sample_rate).expect("Couldn't set global.");
-
-        module.hash = format!("{:x}", module.content.generate_hash());
-
-        return module;
+        return Ok(module);
     }
 
     pub fn init(&mut self) -> LuaResult<(String, String, String)> {
-        let init_contents = format!("{internal}\n{header}\n\n{content}\n\n{footer}", 
-            internal = library::internal_includes(), 
-            header = library::INIT_HEADER, 
-            content = &self.content.init,
-            footer = library::INIT_FOOTER);
-        self.lua.load(init_contents).exec()?;
+        for include in &self.includes {
+            include.call::<()>(())?;
+        }
+        self.init_section.call()?;
 
         // Read additional data
         let globals = self.lua.globals();
@@ -75,87 +156,84 @@ impl RuntimeModule {
         if globals.contains_key(LUA_ABOUT_KEY)? {
             about = globals.get(LUA_ABOUT_KEY)?;
         }
+
+        self.name = name.clone();
+        self.author = authors.clone();
+        self.description = about.clone();
         
         Ok((name, authors, about))
     }
 
     pub fn reset(&mut self) -> LuaResult<()> {
-        let reset_contents = format!("{header}\n\n{content}\n\n{footer}", 
-            header = library::RESET_HEADER, 
-            content = &self.content.reset,
-            footer = library::RESET_FOOTER);
-        self.lua.load(reset_contents).exec()?;
+        self.reset_section.call()?;
         
         Ok(())
     }
 
-    pub fn trigger(&mut self) -> LuaResult<()> {
-        let trigger_contents = format!("{header}\n\n{content}\n\n{footer}", 
-            header = library::TRIGGER_HEADER, 
-            content = &self.content.trigger,
-            footer = library::TRIGGER_FOOTER);
-        self.lua.load(trigger_contents).exec()?;
+    // Offset is the sample within the next run's buffer the trigger lands on, starting at 0.
+    // Lua gets it starting at 1 too, to line up with BUFFER and MIDI event samples.
+    pub fn trigger(&mut self, offset: usize) -> LuaResult<()> {
+        self.lua.globals().set(LUA_TRIGGER_SAMPLE_KEY, offset + 1)?;
+        self.lua.globals().set(LUA_TRIGGER_OFFSET_KEY, offset)?;
+        self.trigger_section.call()?;
         
         Ok(())
     }
 
-    pub fn run(&mut self, buffer : &mut Buffer, input_noise: bool, clip: bool) -> LuaResult<Vec<String>> {
-        self.lua.globals().set(LUA_CHANNELS_KEY,buffer.channels())?;
-        self.lua.globals().set(LUA_BUFFER_SIZE_KEY,buffer.samples())?;
+    // Runs the module in place on channel slices of equal length. The sidechain is only read.
+    pub fn run<T: AsRef<[f32]> + AsMut<[f32]>, S: AsRef<[f32]>>(&mut self, samples: &mut [T], sidechain: &[S], midi_events: &[MidiEvent], transport: &Transport, input_noise: bool, clip: bool) -> LuaResult<Vec<String>> {
+        let channels = samples.len();
+        let size = samples.first().map_or(0, |c| c.as_ref().len());
+
+        self.lua.globals().set(LUA_CHANNELS_KEY, channels)?;
+        self.lua.globals().set(LUA_BUFFER_SIZE_KEY, size)?;
         self.lua.globals().set(LUA_INPUT_NOISE_KEY, input_noise)?;
+        self.update_midi_events(midi_events)?;
+        self.update_transport(transport)?;
         
-        for c in 0..buffer.channels() {
-            if self.channels <= c {
-                let buffer = self.lua.create_table()?;
-                self.lua_buffers.set(c + 1, buffer)?; // Lua indexes start at 1
-                self.channels += 1;
-            }
-        }
-
-        // Write to lua buffers
-        let mut channel_sample_index = 1; // Lua indexes start at 1
-        for channel_samples in buffer.iter_samples() {            
-            let mut channel = 0;
-            for sample in channel_samples {
-                let channel_buffer : LuaTable = self.lua_buffers.get(channel + 1)?; // Lua indexes start at 1
-                channel_buffer.set(channel_sample_index, *sample)?;
-
-                channel += 1;
-            }
-
-            channel_sample_index += 1;
+        // Write to lua buffer
+        {
+            let mut lua_buffer = self.lua_buffer.borrow_mut::<LuaBuffer>()?;
+            lua_buffer.prepare(&self.lua, channels, size)?;
+            lua_buffer.write_from(samples)?;
+
+            let mut lua_sidechain = self.lua_sidechain.borrow_mut::<LuaBuffer>()?;
+            lua_sidechain.prepare(&self.lua, sidechain.len(), size)?;
+            lua_sidechain.write_from(sidechain)?;
         }
         
         // Execute lua run
-        let run_contents = format!("{header}\n\n{content}\n\n{footer}", 
-            header = library::RUN_HEADER, 
-            content = &self.content.run,
-            footer = library::RUN_FOOTER);
-        self.lua.load(run_contents).exec()?;
-
-        // Write from lua buffers to plugin buffer
-        let mut channel_sample_index = 1; // Lua indexes start at 1
-        for channel_samples in buffer.iter_samples() {                        
-            let mut channel = 0;
-            for sample in channel_samples {
-                let channel_buffer: LuaTable = self.lua_buffers.get(channel + 1)?;
-                *sample = if clip {
-                    utils::clip(channel_buffer.get(channel_sample_index)?)
-                } else {
-                    channel_buffer.get(channel_sample_index)?
-                };
-
-                channel += 1;
-            }
+        self.run_section.call()?;
 
-            channel_sample_index += 1;
+        // Read from lua buffer to plugin buffer
+        {
+            let lua_buffer = self.lua_buffer.borrow::<LuaBuffer>()?;
+            lua_buffer.read_into(samples, clip)?;
         }
         
         return self.process_logs();
     }
 
+    pub fn set_layout(&mut self, layout: &ChannelLayout) -> LuaResult<()> {
+        return RuntimeModule::apply_layout(&self.lua, layout);
+    }
+
+    pub fn set_memory_limit(&mut self, memory_limit: usize) -> LuaResult<()> {
+        self.lua.set_memory_limit(memory_limit)?;
+
+        Ok(())
+    }
+
+    pub fn used_memory(&self) -> usize {
+        return self.lua.used_memory();
+    }
+
+    pub fn gc_running(&self) -> bool {
+        return self.lua.gc_is_running();
+    }
+
     pub fn get_parameters(&mut self) -> LuaResult<LuaTable> {
-        return Ok(self.lua.globals().get(LUA_PARAMETERS_KEY)?);
+        return Ok(self.lua.globals().raw_get(LUA_PARAMETERS_KEY)?);
     }
 
     pub fn update_parameter_value_updates(&mut self, parameters: &mut BTreeMap<String, Parameter>) -> LuaResult<()> {
@@ -168,13 +246,86 @@ impl RuntimeModule {
             parameter.1.set_changed(false);
         }
 
-        return Ok(self.lua.globals().set(LUA_PARAMETER_VALUE_UPDATES_KEY, updates_table)?);
+        return Ok(self.lua.globals().raw_set(LUA_PARAMETER_VALUE_UPDATES_KEY, updates_table)?);
+    }
+
+    // Values the host doesn't provide are left nil, the run header fills in defaults.
+    fn update_transport(&mut self, transport: &Transport) -> LuaResult<()> {
+        let globals = self.lua.globals();
+
+        self.time_sig.set(LUA_TIME_SIG_NUMERATOR_KEY, transport.time_sig_numerator)?;
+        self.time_sig.set(LUA_TIME_SIG_DENOMINATOR_KEY, transport.time_sig_denominator)?;
+
+        globals.set(LUA_TEMPO_KEY, transport.tempo)?;
+        globals.set(LUA_TIME_SIG_KEY, &self.time_sig)?;
+        globals.set(LUA_PLAYING_KEY, transport.playing)?;
+        globals.set(LUA_POS_SAMPLES_KEY, transport.pos_samples())?;
+        globals.set(LUA_POS_BEATS_KEY, transport.pos_beats())?;
+        globals.set(LUA_BAR_START_KEY, transport.bar_start_pos_beats())?;
+
+        Ok(())
+    }
+
+    // Only replaces the event list when there's something to replace, so quiet blocks don't allocate.
+    fn update_midi_events(&mut self, midi_events: &[MidiEvent]) -> LuaResult<()> {
+        if midi_events.is_empty() && !self.has_midi_events { return Ok(()); }
+
+        let lua_events = self.lua.create_table_with_capacity(midi_events.len(), 0)?;
+        for event in midi_events {
+            lua_events.push(event.to_lua(&self.lua)?)?;
+        }
+
+        self.lua.globals().set(LUA_MIDI_EVENTS_KEY, lua_events)?;
+        self.has_midi_events = !midi_events.is_empty();
+
+        Ok(())
+    }
+
+    // Queues a value for the run header to hand to Parameter:set_value.
+    pub fn queue_parameter_value(&mut self, name: &str, value: f32) -> LuaResult<()> {
+        let globals = self.lua.globals();
+        let updates = match globals.get::<Option<LuaTable>>(LUA_PARAMETER_VALUE_UPDATES_KEY)? {
+            Some(u) => u,
+            None => {
+                let u = self.lua.create_table()?;
+                globals.set(LUA_PARAMETER_VALUE_UPDATES_KEY, &u)?;
+                u
+            }
+        };
+
+        return updates.set(name, value);
+    }
+
+    // Moves MIDI queued by the module into output, in the order it was sent.
+    pub fn process_midi_output(&mut self, output: &mut Vec<MidiEvent>, buffer_size: usize, max_events: usize) -> LuaResult<()> {
+        let lua_events: LuaTable = self.lua.globals().get(LUA_MIDI_OUT_KEY)?;
+        if lua_events.is_empty() { return Ok(()); }
+
+        for lua_event in lua_events.sequence_values::<LuaTable>() {
+            if output.len() >= max_events { break; }
+
+            let event = MidiEvent::from_lua(&lua_event?, buffer_size)?;
+            self.active_notes.track(&event);
+            output.push(event);
+        }
+
+        // Clear queue
+        self.lua.globals().set(LUA_MIDI_OUT_KEY, self.lua.create_table()?)?;
+        self.lua.globals().set(LUA_MIDI_OUT_COUNT_KEY, 0)?;
+
+        Ok(())
+    }
+
+    // Hands over the notes this module left on, so they can be turned off once it stops.
+    pub fn release_notes(&mut self, released: &mut ActiveNotes) {
+        released.merge(&mut self.active_notes);
     }
 
     fn process_logs(&mut self) -> LuaResult<Vec<String>> {
         // Get logs
         let mut logs = Vec::new();
         let lua_logs: LuaTable = self.lua.globals().get(LUA_LOGS_KEY)?;
+        if lua_logs.is_empty() { return Ok(logs); }
 
         for log_pair in lua_logs.pairs::<String, String>() {
             let (_key, value) = log_pair?;
@@ -186,4 +337,74 @@ impl RuntimeModule {
 
         Ok(logs)
     }
-}
\ No newline at end of file
+
+    fn apply_layout(lua: &Lua, layout: &ChannelLayout) -> LuaResult<()> {
+        lua.globals().set(LUA_LAYOUT_KEY, layout.name)?;
+        lua.globals().set(LUA_INPUT_CHANNELS_KEY, layout.input_channels)?;
+        lua.globals().set(LUA_CHANNEL_NAMES_KEY, lua.create_sequence_from(layout.channel_names.iter().copied())?)?;
+
+        Ok(())
+    }
+
+    fn compile(lua: &Lua, contents: &str, name: &str) -> LuaResult<LuaFunction> {
+        return lua.load(contents).set_name(name).into_function();
+    }
+}
+
+impl ModuleSection {
+    // Each part is a pair of source and chunk name.
+    fn compile(lua: &Lua, header: (&str, &str), content: (&str, &str), footer: (&str, &str)) -> LuaResult<ModuleSection> {
+        let section = Self {
+            header: RuntimeModule::compile(lua, header.0, &library::internal_chunk_name(header.1))?,
+            content: RuntimeModule::compile(lua, content.0, content.1)?,
+            footer: RuntimeModule::compile(lua, footer.0, &library::internal_chunk_name(footer.1))?
+        };
+
+        return Ok(section);
+    }
+
+    fn call(&self) -> LuaResult<()> {
+        self.header.call::<()>(())?;
+        self.content.call::<()>(())?;
+        self.footer.call::<()>(())?;
+
+        Ok(())
+    }
+}
+
+pub fn is_memory_error(error: &LuaError) -> bool {
+    match error {
+        LuaError::MemoryError(_) => true,
+        LuaError::CallbackError { cause, .. } => is_memory_error(cause),
+        _ => false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::module_content::tests::test_content;
+
+    #[test]
+    fn parameter_reads_skip_index_metamethods() {
+        let watchdog = Watchdog::new();
+        let mut content = test_content();
+        content.init = String::from("
+            local looping = Parameter:new(\"Looping\", 0.5, 0.0, 1.0);
+            setmetatable(looping, { __index = function() while true do end end });
+            setmetatable(PARAMETERS, { __index = function() while true do end end });");
+
+        let mut module = RuntimeModule::new(content, 48000.0, &ChannelLayout::stereo(), &watchdog, DEFAULT_MEMORY_LIMIT, SandboxLevel::Sandboxed, None, &EntryFiles::default(), Vec::new()).unwrap();
+        module.init().unwrap();
+
+        // Reading through __index would loop until the watchdog trips.
+        watchdog.arm(1000.0);
+        let parameters = module.get_parameters().unwrap();
+        let parameter = Parameter::new_from_lua(&parameters.raw_get::<LuaTable>("Looping").unwrap());
+        watchdog.disarm();
+
+        assert!(!watchdog.tripped());
+        assert!(parameter.is_ok());
+        assert!(parameter.unwrap().slot.is_none());
+    }
+}
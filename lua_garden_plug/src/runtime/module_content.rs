@@ -1,9 +1,13 @@
 use std::hash::{ DefaultHasher, Hasher };
 use base64::{engine::general_purpose::URL_SAFE, Engine as _};
+use serde::{ Deserialize, Serialize };
+use xxhash_rust::xxh3::Xxh3;
 
 const ENCODING_SEPERATOR: char = '\\';
+const ENCODING_PARTS: usize = 6;
+const SECTION_NAMES: [&str; 5] = ["init", "reset", "trigger", "run", "interface"];
 
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub struct ModuleContent {
     pub init: String,
     pub reset: String,
@@ -34,13 +38,24 @@ impl ModuleContent {
     }
 
     pub fn generate_hash(&self) -> u64 {
-        let mut hasher = DefaultHasher::new();
-        hasher.write(self.init.as_bytes());
-        hasher.write(self.reset.as_bytes());
-        hasher.write(self.trigger.as_bytes());
-        hasher.write(self.run.as_bytes());
-        
-        return hasher.finish();
+        return self.generate_hash_with_files(&[]);
+    }
+
+    // XXH3 (64 bit, seed 0) is specified and stable, so hashes match across builds and platforms.
+    // Every section and extra file is hashed as its name, a zero byte, its length and its contents,
+    // so moving code between sections changes the hash too.
+    pub fn generate_hash_with_files(&self, extra_files: &[(&str, &str)]) -> u64 {
+        let sections = [self.init.as_str(), self.reset.as_str(), self.trigger.as_str(), self.run.as_str(), self.interface.as_str()];
+
+        let mut hasher = Xxh3::new();
+        for (name, contents) in SECTION_NAMES.iter().zip(sections) {
+            ModuleContent::hash_part(&mut hasher, name, contents);
+        }
+        for (name, contents) in extra_files {
+            ModuleContent::hash_part(&mut hasher, name, contents);
+        }
+
+        return hasher.digest();
     }
 
     pub fn to_base64(&self) -> String{
@@ -62,6 +77,71 @@ impl ModuleContent {
             
         return base64;
     }
+
+    // Decodes a string made by to_base64, checking it against the hash it carries.
+    pub fn from_base64(encoded: &str) -> Result<ModuleContent, String> {
+        let parts: Vec<&str> = encoded.trim().split(ENCODING_SEPERATOR).collect();
+        if parts.len() != ENCODING_PARTS {
+            return Err(format!("Expected {expected} parts separated by \"{sp}\", found {found}.", 
+                expected = ENCODING_PARTS, 
+                sp = ENCODING_SEPERATOR, 
+                found = parts.len()));
+        }
+
+        let hash = match u64::from_str_radix(parts[0], 16) {
+            Ok(h) => h,
+            Err(_e) => return Err(format!("\"{}\" isn't a valid hash.", parts[0]))
+        };
+
+        let mut sections = Vec::with_capacity(SECTION_NAMES.len());
+        for (name, part) in SECTION_NAMES.iter().zip(&parts[1..]) {
+            let bytes = match URL_SAFE.decode(part) {
+                Ok(b) => b,
+                Err(e) => return Err(format!("The {name} section isn't valid base64: {e}"))
+            };
+
+            match String::from_utf8(bytes) {
+                Ok(s) => sections.push(s),
+                Err(_e) => return Err(format!("The {name} section isn't valid text."))
+            }
+        }
+
+        let mut sections = sections.into_iter();
+        let content = ModuleContent::new(
+            sections.next().unwrap_or_default(),
+            sections.next().unwrap_or_default(),
+            sections.next().unwrap_or_default(),
+            sections.next().unwrap_or_default(),
+            sections.next().unwrap_or_default());
+
+        // Strings from before the stable hash carry the legacy one, which they are still checked against.
+        let content_hash = content.generate_hash();
+        if content_hash != hash && content.generate_legacy_hash() != hash {
+            return Err(format!("Hash mismatch, expected {expected:x} but the content hashes to {found:x}. The string is damaged or was changed.", 
+                expected = hash, 
+                found = content_hash));
+        }
+
+        return Ok(content);
+    }
+
+    // The hash share strings carried before XXH3. Skips the interface section.
+    fn generate_legacy_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(self.init.as_bytes());
+        hasher.write(self.reset.as_bytes());
+        hasher.write(self.trigger.as_bytes());
+        hasher.write(self.run.as_bytes());
+
+        return hasher.finish();
+    }
+
+    fn hash_part(hasher: &mut Xxh3, name: &str, contents: &str) {
+        hasher.update(name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(&(contents.len() as u64).to_le_bytes());
+        hasher.update(contents.as_bytes());
+    }
 }
 
 impl<'a> ConstModuleContent<'a> {
@@ -83,4 +163,76 @@ impl<'a> ConstModuleContent<'a> {
             String::from(self.run),
             String::from(self.interface));
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    // Shared by the tests of everything that encodes modules.
+    pub(crate) fn test_content() -> ModuleContent {
+        return ModuleContent::new(
+            String::from("MODULE_NAME = \"Test\""),
+            String::from("-- reset"),
+            String::from("-- trigger"),
+            String::from("iterate(function(s) return s end)"),
+            String::from("-- interface"));
+    }
+
+    fn encode_with_hash(content: &ModuleContent, hash: u64) -> String {
+        let encoded = content.to_base64();
+        let (_hash, sections) = encoded.split_once(ENCODING_SEPERATOR).unwrap();
+
+        return format!("{hash:x}{sp}{sections}", hash = hash, sp = ENCODING_SEPERATOR, sections = sections);
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let content = test_content();
+        let decoded = ModuleContent::from_base64(&content.to_base64()).unwrap();
+
+        assert!(decoded == content);
+    }
+
+    #[test]
+    fn wrong_hash_is_rejected() {
+        let content = test_content();
+        let encoded = encode_with_hash(&content, content.generate_hash() ^ 1);
+
+        assert!(ModuleContent::from_base64(&encoded).is_err());
+    }
+
+    #[test]
+    fn missing_sections_are_rejected() {
+        let encoded = test_content().to_base64();
+        let (truncated, _interface) = encoded.rsplit_once(ENCODING_SEPERATOR).unwrap();
+
+        assert!(ModuleContent::from_base64(truncated).is_err());
+    }
+
+    #[test]
+    fn legacy_hash_is_accepted() {
+        let content = test_content();
+        let encoded = encode_with_hash(&content, content.generate_legacy_hash());
+
+        assert!(ModuleContent::from_base64(&encoded).unwrap() == content);
+    }
+
+    #[test]
+    fn hash_covers_every_section() {
+        let content = test_content();
+        let mut changed = content.clone();
+        changed.interface.push_str(" changed");
+
+        assert_ne!(content.generate_hash(), changed.generate_hash());
+    }
+
+    #[test]
+    fn hash_covers_extra_files() {
+        let content = test_content();
+
+        assert_eq!(content.generate_hash(), content.generate_hash_with_files(&[]));
+        assert_ne!(content.generate_hash(), content.generate_hash_with_files(&[("lib.lua", "")]));
+        assert_ne!(content.generate_hash_with_files(&[("lib.lua", "a")]), content.generate_hash_with_files(&[("lib.lua", "b")]));
+    }
+}
@@ -0,0 +1,141 @@
+use std::{ collections::BTreeMap, sync::{ atomic::{ AtomicBool, Ordering }, Arc, RwLock } };
+use nih_plug::params::persist::PersistentField;
+use serde::{ Deserialize, Serialize };
+
+use crate::interface::{ interface_data::InterfaceData, InterfaceMode };
+use super::{ module_content::ModuleContent, preset::PresetBank, runtime_data::{ RuntimeData, RuntimeState }, sandbox::SandboxLevel };
+
+// The loaded module as saved in the plugin state, so projects reopen with it.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct ModuleState {
+    // The draft, or a fallback copy of the workspace in case its folder is gone.
+    pub content: Option<ModuleContent>,
+    pub workspace_path: Option<String>,
+    pub sandbox: SandboxLevel,
+
+    pub online: bool,
+    pub clip: bool,
+    pub input_noise: bool,
+
+    pub parameters: BTreeMap<String, f32>,
+    #[serde(default)]
+    pub draft_presets: BTreeMap<String, PresetBank>,
+    #[serde(default)]
+    pub library_path: Option<String>
+}
+
+impl ModuleState {
+    pub fn new() -> ModuleState {
+        Self {
+            content: None,
+            workspace_path: None,
+            sandbox: SandboxLevel::Sandboxed,
+
+            online: false,
+            clip: true,
+            input_noise: false,
+
+            parameters: BTreeMap::new(),
+            draft_presets: BTreeMap::new(),
+            library_path: None
+        }
+    }
+
+    pub fn from_data(runtime_data: &RuntimeData, interface_data: &InterfaceData) -> ModuleState {
+        let mut state = ModuleState::new();
+
+        match (&interface_data.mode, &interface_data.workspace) {
+            (InterfaceMode::Workspace, Some(workspace)) => {
+                state.content = Some(workspace.content.clone());
+                state.workspace_path = Some(workspace.path.clone());
+                state.sandbox = workspace.sandbox;
+            },
+            _ => {
+                state.content = Some(interface_data.draft_content.clone());
+            }
+        }
+
+        state.online = runtime_data.state == RuntimeState::Online || runtime_data.state == RuntimeState::Bypass;
+        state.clip = runtime_data.clip;
+        state.input_noise = runtime_data.input_noise;
+
+        for parameter in &runtime_data.parameters {
+            state.parameters.insert(parameter.0.clone(), parameter.1.value);
+        }
+
+        state.draft_presets = interface_data.draft_presets.clone();
+        state.library_path = Some(interface_data.library_path.clone());
+
+        return state;
+    }
+}
+
+impl Default for ModuleState {
+    fn default() -> Self {
+        ModuleState::new()
+    }
+}
+
+// Persists the module state. The snapshot is built when the host saves, so the audio thread never has to.
+pub struct ModuleStateField {
+    restored: RwLock<ModuleState>,
+    // Whether the host handed us a state that hasn't been restored yet.
+    pending: AtomicBool,
+
+    runtime_data: Arc<RwLock<RuntimeData>>,
+    interface_data: Arc<RwLock<InterfaceData>>
+}
+
+impl ModuleStateField {
+    pub fn new(runtime_data: Arc<RwLock<RuntimeData>>, interface_data: Arc<RwLock<InterfaceData>>) -> ModuleStateField {
+        Self {
+            restored: RwLock::new(ModuleState::new()),
+            pending: AtomicBool::new(false),
+
+            runtime_data: runtime_data,
+            interface_data: interface_data
+        }
+    }
+
+    // The state the host loaded, once.
+    pub fn take_pending(&self) -> Option<ModuleState> {
+        if !self.pending.swap(false, Ordering::AcqRel) { return None; }
+
+        return match self.restored.read() {
+            Ok(s) => Some(s.clone()),
+            Err(_e) => None
+        };
+    }
+
+    fn snapshot(&self) -> Option<ModuleState> {
+        let runtime_data = self.runtime_data.read().ok()?;
+        let interface_data = self.interface_data.read().ok()?;
+
+        return Some(ModuleState::from_data(&runtime_data, &interface_data));
+    }
+}
+
+impl<'a> PersistentField<'a, ModuleState> for ModuleStateField {
+    fn set(&self, new_value: ModuleState) {
+        match self.restored.write() {
+            Ok(mut s) => *s = new_value,
+            Err(_e) => return
+        }
+        self.pending.store(true, Ordering::Release);
+    }
+
+    fn map<F, R>(&self, f: F) -> R where F: Fn(&ModuleState) -> R {
+        // Until a loaded state is restored, it's still the one to save.
+        let snapshot = if self.pending.load(Ordering::Acquire) { None } else { self.snapshot() };
+
+        return match snapshot {
+            Some(state) => f(&state),
+            None => {
+                match self.restored.read() {
+                    Ok(s) => f(&s),
+                    Err(_e) => f(&ModuleState::new())
+                }
+            }
+        };
+    }
+}
@@ -5,6 +5,8 @@ const LUA_VALUE_KEY: &str = "value";
 const LUA_MIN_KEY: &str = "min";
 const LUA_MAX_KEY: &str = "max";
 const LUA_STEP_SIZE_KEY: &str = "step_size";
+const LUA_ORDER_KEY: &str = "order";
+const LUA_SLOT_KEY: &str = "slot";
 
 #[derive(Clone)]
 pub struct Parameter {
@@ -13,6 +15,12 @@ pub struct Parameter {
     pub min: f32,
     pub max: f32,
     pub step_size: f32,
+
+    // Registration order and the macro slot the module asked for, starting at 1.
+    pub order: usize,
+    pub slot: Option<usize>,
+    // The macro slot the parameter ended up on, starting at 0.
+    pub mapped_slot: Option<usize>,
     
     pub changed: bool
 }
@@ -25,19 +33,28 @@ impl Parameter {
             min: min,
             max: max,
             step_size: step_size,
+
+            order: 0,
+            slot: None,
+            mapped_slot: None,
             
             changed: false
         }
     }
 
+    // Raw reads, so a metamethod in the module can't run here. Parameter:new puts every field on the instance.
     pub fn new_from_lua(lua_parameter: &Table) -> LuaResult<Parameter> {
-        let name: String = lua_parameter.get(LUA_NAME_KEY)?;
-        let value: f32 = lua_parameter.get(LUA_VALUE_KEY)?;
-        let min: f32 = lua_parameter.get(LUA_MIN_KEY)?;
-        let max: f32 = lua_parameter.get(LUA_MAX_KEY)?;
-        let step_size: f32 = lua_parameter.get(LUA_STEP_SIZE_KEY)?;
+        let name: String = lua_parameter.raw_get(LUA_NAME_KEY)?;
+        let value: f32 = lua_parameter.raw_get(LUA_VALUE_KEY)?;
+        let min: f32 = lua_parameter.raw_get(LUA_MIN_KEY)?;
+        let max: f32 = lua_parameter.raw_get(LUA_MAX_KEY)?;
+        let step_size: f32 = lua_parameter.raw_get(LUA_STEP_SIZE_KEY)?;
+
+        let mut parameter = Parameter::new(name, value, min, max, step_size);
+        parameter.order = lua_parameter.raw_get::<Option<usize>>(LUA_ORDER_KEY)?.unwrap_or(0);
+        parameter.slot = lua_parameter.raw_get(LUA_SLOT_KEY)?;
 
-        return Ok(Parameter::new(name, value, min, max, step_size));
+        return Ok(parameter);
     }
 
     pub fn update_from_parameter(&mut self, parameter: &Parameter) {
@@ -0,0 +1,113 @@
+use std::{ collections::BTreeMap, fs, io };
+use serde::{ Deserialize, Serialize };
+
+use super::{ library, parameter::Parameter };
+
+// A named snapshot of a module's parameter values.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct Preset {
+    pub name: String,
+    pub values: BTreeMap<String, f32>
+}
+
+// The presets of a single module. Workspaces keep theirs in a file, drafts in the plugin state.
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct PresetBank {
+    pub presets: Vec<Preset>
+}
+
+impl Preset {
+    pub fn from_parameters(name: String, parameters: &BTreeMap<String, Parameter>) -> Preset {
+        let mut values = BTreeMap::new();
+        for parameter in parameters {
+            values.insert(parameter.0.clone(), parameter.1.value);
+        }
+
+        Self {
+            name: name,
+            values: values
+        }
+    }
+
+    // Sets parameters to this preset's values and marks them changed. Parameters the preset doesn't know are left alone.
+    pub fn apply(&self, parameters: &mut BTreeMap<String, Parameter>) {
+        for parameter in parameters {
+            match self.values.get(parameter.0) {
+                Some(value) => {
+                    parameter.1.value = f32::clamp(*value, parameter.1.min, parameter.1.max);
+                    parameter.1.set_changed(true);
+                },
+                None => ()
+            }
+        }
+    }
+
+    // Interpolates between two presets. At 0 it's from, at 1 it's to.
+    pub fn morph(from: &Preset, to: &Preset, t: f32, parameters: &mut BTreeMap<String, Parameter>) {
+        let t = f32::clamp(t, 0.0, 1.0);
+
+        for parameter in parameters {
+            let value = match (from.values.get(parameter.0), to.values.get(parameter.0)) {
+                (Some(a), Some(b)) => a + (b - a) * t,
+                (Some(a), None) => *a,
+                (None, Some(b)) => *b,
+                (None, None) => continue
+            };
+
+            parameter.1.value = f32::clamp(value, parameter.1.min, parameter.1.max);
+            parameter.1.set_changed(true);
+        }
+    }
+}
+
+impl PresetBank {
+    pub fn new() -> PresetBank {
+        Self {
+            presets: Vec::new()
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Preset> {
+        return self.presets.iter().find(|p| p.name == name);
+    }
+
+    // Replaces a preset with the same name, if there is one.
+    pub fn save(&mut self, preset: Preset) {
+        match self.presets.iter_mut().find(|p| p.name == preset.name) {
+            Some(p) => *p = preset,
+            None => self.presets.push(preset)
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.presets.retain(|p| p.name != name);
+    }
+
+    // A workspace without a presets file has no presets yet.
+    pub fn load_from_workspace(path: &str) -> Result<PresetBank, String> {
+        let file_path = format!("{path}/{file}", path = path, file = library::PRESETS_PATH);
+        let contents = match fs::read_to_string(&file_path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(PresetBank::new()),
+            Err(e) => return Err(format!("Couldn't read presets: {}", e))
+        };
+
+        match serde_json::from_str(&contents) {
+            Ok(bank) => return Ok(bank),
+            Err(e) => return Err(format!("Couldn't parse presets: {}", e))
+        }
+    }
+
+    pub fn save_to_workspace(&self, path: &str) -> Result<(), String> {
+        let file_path = format!("{path}/{file}", path = path, file = library::PRESETS_PATH);
+        let contents = match serde_json::to_string_pretty(self) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't serialize presets: {}", e))
+        };
+
+        match fs::write(&file_path, contents) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(format!("Couldn't write presets: {}", e))
+        }
+    }
+}
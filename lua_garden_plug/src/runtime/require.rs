@@ -0,0 +1,104 @@
+use mlua::prelude::*;
+
+use super::{ library::{ self, LuaFiles }, sandbox::SandboxLevel };
+
+const LUA_PACKAGE_KEY: &str = "package";
+const LUA_SEARCHERS_KEY: &str = "searchers";
+const LUA_REQUIRE_KEY: &str = "require";
+const SEARCHER_REGISTRY_KEY: &str = "lua_garden_searcher";
+const LOADED_REGISTRY_KEY: &str = "lua_garden_loaded";
+const LUA_EXTENSION: &str = "lua";
+const PACKAGE_INIT_FILE: &str = "init.lua";
+
+// Lets modules require lua files from the given folders, with their files read ahead of time.
+// Trusted modules get a package.searchers entry. Sandboxed modules have no package library,
+// so they get a require that only searches these folders.
+pub fn install(lua: &Lua, roots: Vec<(String, LuaFiles)>, sandbox: SandboxLevel) -> LuaResult<()> {
+    let searcher = create_searcher(lua, roots)?;
+
+    match sandbox {
+        SandboxLevel::Trusted => {
+            let package: LuaTable = lua.globals().get(LUA_PACKAGE_KEY)?;
+            let searchers: LuaTable = package.get(LUA_SEARCHERS_KEY)?;
+
+            // After package.preload, before the default searchers.
+            searchers.raw_insert(2, searcher)?;
+        },
+        SandboxLevel::Sandboxed => {
+            lua.set_named_registry_value(SEARCHER_REGISTRY_KEY, searcher)?;
+            lua.set_named_registry_value(LOADED_REGISTRY_KEY, lua.create_table()?)?;
+            lua.globals().set(LUA_REQUIRE_KEY, lua.create_function(require)?)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Follows the searcher protocol. Returns a loader and the file it came from, or a message listing the files tried.
+fn create_searcher(lua: &Lua, roots: Vec<(String, LuaFiles)>) -> LuaResult<LuaFunction> {
+    return lua.create_function(move |lua, name: String| {
+        let files = match module_files(&name) {
+            Some(f) => f,
+            None => return Ok((LuaValue::String(lua.create_string(format!("\n\tinvalid module name '{}'", name))?), LuaNil))
+        };
+
+        let mut tried = String::new();
+        for (root, root_files) in &roots {
+            for file in &files {
+                match root_files.get(file) {
+                    Some(source) => {
+                        let loader = lua.load(source.as_str()).set_name(library::chunk_name(Some(root), file)).into_function()?;
+                        let path = lua.create_string(format!("{root}/{file}", root = root, file = file))?;
+
+                        return Ok((LuaValue::Function(loader), LuaValue::String(path)));
+                    },
+                    None => tried.push_str(&format!("\n\tno file '{root}/{file}'", root = root, file = file))
+                }
+            }
+        }
+
+        if roots.is_empty() {
+            tried.push_str("\n\tno folders to search, require needs a workspace or a user library");
+        }
+
+        return Ok((LuaValue::String(lua.create_string(tried)?), LuaNil));
+    });
+}
+
+// A minimal require for sandboxed modules, caching what each module returns like the real one.
+fn require(lua: &Lua, name: String) -> LuaResult<LuaValue> {
+    let loaded: LuaTable = lua.named_registry_value(LOADED_REGISTRY_KEY)?;
+    let cached: LuaValue = loaded.get(name.as_str())?;
+    if !cached.is_nil() {
+        return Ok(cached);
+    }
+
+    let searcher: LuaFunction = lua.named_registry_value(SEARCHER_REGISTRY_KEY)?;
+    let (loader, data): (LuaValue, LuaValue) = searcher.call(name.as_str())?;
+    let loader = match loader {
+        LuaValue::Function(f) => f,
+        LuaValue::String(s) => return Err(LuaError::runtime(format!("module '{name}' not found:{tried}", name = name, tried = &*s.to_str()?))),
+        _ => return Err(LuaError::runtime(format!("module '{}' not found", name)))
+    };
+
+    let result: LuaValue = loader.call((name.as_str(), data))?;
+    let result = if result.is_nil() { LuaValue::Boolean(true) } else { result };
+    loaded.set(name.as_str(), &result)?;
+
+    return Ok(result);
+}
+
+// "lib.delay" can be lib/delay.lua or lib/delay/init.lua. Names that could leave the folder are refused.
+fn module_files(name: &str) -> Option<[String; 2]> {
+    let valid_characters = name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+    if name.is_empty() || !valid_characters || name.split('.').any(|s| s.is_empty()) {
+        return None;
+    }
+
+    let path = name.replace('.', "/");
+
+    return Some([
+        format!("{path}.{extension}", path = path, extension = LUA_EXTENSION),
+        format!("{path}/{file}", path = path, file = PACKAGE_INIT_FILE)
+    ]);
+}
\ No newline at end of file
@@ -4,14 +4,15 @@ use mlua::Table;
 
 use crate::interface::interface_data::InterfaceData;
 
-use super::{parameter::Parameter, Runtime};
+use super::{crossfade, module, parameter::Parameter, sandbox::SandboxLevel, watchdog, Runtime};
 
 #[derive(Clone, PartialEq)]
 pub enum RuntimeState {
     Offline,
     Refresh,
     Clear,
-    Online
+    Online,
+    Bypass
 }
 
 #[derive(Clone)]
@@ -21,13 +22,25 @@ pub struct RuntimeData {
     pub sample_rate: f32,
     pub buffer_size: usize,
     pub channels: usize,
+    pub layout: &'static str,
     pub run_ms: f32,
+    pub memory_used: usize,
+    pub memory_peak: usize,
+    pub memory_drops: u32,
+    pub gc_freed: usize,
+    pub gc_running: bool,
     pub input_noise: bool,
     pub clip: bool,
+    pub watchdog_budget: f32,
+    pub memory_limit: usize,
+    pub crossfade_ms: f32,
+    pub triggers: u32,
 
     pub module_name: String,
     pub module_author: String,
     pub module_description: String,
+    pub sandbox: Option<SandboxLevel>,
+    pub error: Option<String>,
 
     pub parameters: BTreeMap<String, Parameter>,
 
@@ -43,13 +56,25 @@ impl RuntimeData {
             sample_rate: 0.0,
             buffer_size: 0,
             channels: 0,
+            layout: "",
             run_ms: 0.0,
+            memory_used: 0,
+            memory_peak: 0,
+            memory_drops: 0,
+            gc_freed: 0,
+            gc_running: false,
             input_noise: false,
             clip: true,
+            watchdog_budget: watchdog::DEFAULT_BUDGET,
+            memory_limit: module::DEFAULT_MEMORY_LIMIT,
+            crossfade_ms: crossfade::DEFAULT_CROSSFADE_MS,
+            triggers: 0,
 
             module_name: String::new(),
             module_author: String::new(),
             module_description: String::new(),
+            sandbox: None,
+            error: None,
             
             parameters: BTreeMap::new(),
             
@@ -64,6 +89,10 @@ impl RuntimeData {
         self.state = interface_data.runtime_target_state.clone();
         self.clip = interface_data.runtime_clip;
         self.input_noise = interface_data.runtime_input_noise;
+        self.watchdog_budget = interface_data.runtime_watchdog_budget;
+        self.memory_limit = interface_data.runtime_memory_limit;
+        self.crossfade_ms = interface_data.runtime_crossfade_ms;
+        self.triggers = interface_data.runtime_triggers;
     }
 
     pub fn update_from_runtime(&mut self, runtime: &mut Runtime, interface_data: &InterfaceData) {
@@ -74,22 +103,26 @@ impl RuntimeData {
         self.module_description = runtime.description.clone();
         
         // TODO only copy when refresh from interface is required.
-        match &mut runtime.module {
-            Some(m) => {
-                match m.get_parameters() {
-                    Ok(p) => {
-                        self.update_parameters(interface_data, p);
-                    },
-                    Err(e) => {
-                        println!("Failed to copy parameters from lua {}", e);
-                    }
+        let read_result = runtime.read_module(|m| {
+            match m.get_parameters() {
+                Ok(p) => {
+                    self.update_parameters(interface_data, p);
+                },
+                Err(e) => {
+                    println!("Failed to copy parameters from lua {}", e);
                 }
+            }
+
+            return m.update_parameter_value_updates(&mut self.parameters);
+        });
+
+        match read_result {
+            Some(Ok(())) => (),
+            Some(Err(e)) => {
+                println!("Failed to update lua parameter value updates: {}.", e);
 
-                match m.update_parameter_value_updates(&mut self.parameters) {
-                    Ok(()) => (),
-                    Err(e) => {
-                        println!("Failed to update lua parameter value updates: {}.", e);
-                    }
+                if runtime.exceeded_budget() && self.state == RuntimeState::Online {
+                    self.state = runtime.failed_state();
                 }
             },
             None => {
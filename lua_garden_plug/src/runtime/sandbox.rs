@@ -0,0 +1,115 @@
+use mlua::prelude::*;
+use serde::{ Deserialize, Serialize };
+
+const SANDBOXED_GLOBALS: [&str; 27] = [
+    "_G", "_VERSION",
+    "assert", "error", "collectgarbage", "getmetatable", "setmetatable", "ipairs", "pairs", "next", "pcall", "xpcall", "print",
+    "rawequal", "rawget", "rawset", "rawlen", "select", "tonumber", "tostring", "type",
+    "string", "table", "math", "utf8", "coroutine", "os"
+];
+
+// Only the clock and date functions of os are safe to hand out.
+const SANDBOXED_OS_FUNCTIONS: [&str; 4] = ["clock", "time", "date", "difftime"];
+
+// Stopping or tuning the collector would get around the memory accounting.
+const SANDBOXED_GC_OPTIONS: [&str; 2] = ["count", "step"];
+
+const LUA_OS_KEY: &str = "os";
+const LUA_COLLECTGARBAGE_KEY: &str = "collectgarbage";
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum SandboxLevel {
+    // Whitelisted globals only. No file access, no require, no os.execute.
+    Sandboxed,
+    // The full safe standard library, including io, os and require.
+    Trusted
+}
+
+impl SandboxLevel {
+    pub fn name(&self) -> &'static str {
+        return match self {
+            SandboxLevel::Sandboxed => "Sandboxed",
+            SandboxLevel::Trusted => "Trusted"
+        };
+    }
+
+    // Case insensitive, for hand written files.
+    pub fn from_name(name: &str) -> Option<SandboxLevel> {
+        return match name.to_lowercase().as_str() {
+            "sandboxed" => Some(SandboxLevel::Sandboxed),
+            "trusted" => Some(SandboxLevel::Trusted),
+            _ => None
+        };
+    }
+
+    pub fn description(&self) -> &'static str {
+        return match self {
+            SandboxLevel::Sandboxed => "The module runs with a whitelisted set of globals.\nIt can't access files or run programs.",
+            SandboxLevel::Trusted => "The module runs with the full standard library.\nIt can access files and run programs."
+        };
+    }
+
+    pub fn create_lua(&self) -> LuaResult<Lua> {
+        match self {
+            SandboxLevel::Sandboxed => {
+                let lua = Lua::new_with(SandboxLevel::sandboxed_libs(), LuaOptions::default())?;
+                SandboxLevel::apply_whitelist(&lua)?;
+
+                return Ok(lua);
+            },
+            SandboxLevel::Trusted => {
+                return Ok(Lua::new());
+            }
+        }
+    }
+
+    // Libraries loaded for sandboxed modules. Anything not whitelisted is removed afterwards.
+    fn sandboxed_libs() -> LuaStdLib {
+        return LuaStdLib::TABLE | LuaStdLib::STRING | LuaStdLib::MATH | LuaStdLib::UTF8 | LuaStdLib::COROUTINE | LuaStdLib::OS;
+    }
+
+    fn apply_whitelist(lua: &Lua) -> LuaResult<()> {
+        let globals = lua.globals();
+
+        // Restrict os
+        let os: LuaTable = globals.get(LUA_OS_KEY)?;
+        let sandboxed_os = lua.create_table()?;
+        for function in SANDBOXED_OS_FUNCTIONS {
+            sandboxed_os.set(function, os.get::<LuaValue>(function)?)?;
+        }
+        globals.set(LUA_OS_KEY, sandboxed_os)?;
+
+        // Restrict collectgarbage
+        let collectgarbage: LuaFunction = globals.get(LUA_COLLECTGARBAGE_KEY)?;
+        let sandboxed_collectgarbage = lua.create_function(move |_lua, (option, argument): (Option<String>, LuaValue)| {
+            let option = option.unwrap_or_else(|| String::from("collect"));
+            if !SANDBOXED_GC_OPTIONS.contains(&option.as_str()) {
+                return Err(LuaError::runtime(format!("collectgarbage(\"{option}\") isn't available in the sandbox.", option = option)));
+            }
+
+            return collectgarbage.call::<LuaMultiValue>((option, argument));
+        })?;
+        globals.set(LUA_COLLECTGARBAGE_KEY, sandboxed_collectgarbage)?;
+
+        // Remove globals that aren't whitelisted
+        let mut removed = Vec::new();
+        for pair in globals.pairs::<LuaValue, LuaValue>() {
+            let (key, _value) = pair?;
+
+            let whitelisted = match &key {
+                LuaValue::String(s) => SANDBOXED_GLOBALS.contains(&&*s.to_str()?),
+                _ => false
+            };
+
+            if !whitelisted {
+                removed.push(key);
+            }
+        }
+
+        for key in removed {
+            globals.raw_set(key, LuaNil)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use std::{ fs, path::Path };
+use super::library::{ self, LuaFiles };
+
+// A plain text file whose first line names the library's version.
+pub const VERSION_PATH: &str = "VERSION";
+
+// A shared folder of lua files any module can require, so helpers don't need to be copied into every module.
+#[derive(Clone)]
+pub struct UserLibrary {
+    pub path: String,
+    pub version: Option<String>,
+    // Read when scanning, so modules can require them without touching the disk.
+    pub files: LuaFiles
+}
+
+impl UserLibrary {
+    // The library is optional, so a missing folder is not an error.
+    pub fn scan(path: &str) -> Option<UserLibrary> {
+        if path.is_empty() || !Path::new(path).is_dir() {
+            return None;
+        }
+
+        let version = fs::read_to_string(format!("{path}/{file}", path = path, file = VERSION_PATH)).ok()
+            .and_then(|v| v.lines().next().map(|l| String::from(l.trim())))
+            .filter(|v| !v.is_empty());
+
+        return Some(UserLibrary {
+            path: String::from(path),
+            version: version,
+            files: library::read_lua_files(path)
+        });
+    }
+
+    pub fn describe(&self) -> String {
+        let version = match &self.version {
+            Some(v) => format!("version {}", v),
+            None => String::from("no version")
+        };
+
+        return format!("User library at {path} ({version}, {count} files).", path = self.path, version = version, count = self.files.len());
+    }
+}
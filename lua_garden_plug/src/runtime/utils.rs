@@ -4,6 +4,10 @@ pub fn clip(input: f32) -> f32 {
     return f32::clamp(input, -1.0, 1.0)
 }
 
+pub fn bytes_to_mb(bytes: usize) -> f32 {
+    return bytes as f32 / (1024.0 * 1024.0);
+}
+
 pub struct Timer {
     pub instant: Instant
 }
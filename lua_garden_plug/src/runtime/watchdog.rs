@@ -0,0 +1,96 @@
+use std::{ sync::{ atomic::{ AtomicBool, AtomicU64, Ordering }, Arc }, time::Instant };
+use mlua::prelude::*;
+
+pub const DEFAULT_BUDGET: f32 = 0.8; // Fraction of the buffer's time budget a run may use.
+pub const MIN_BUDGET: f32 = 0.1;
+pub const MAX_BUDGET: f32 = 4.0;
+pub const INIT_BUDGET_MS: f32 = 1000.0; // Init and reset may do intense work, but not forever.
+const INSTRUCTION_INTERVAL: u32 = 1000;
+const DISARMED: u64 = u64::MAX;
+const EXCEEDED_BUDGET_MESSAGE: &str = "Module exceeded budget.";
+// Functions that catch errors, wrapped so they can't swallow the watchdog's. Coroutines can't yield through the wrappers.
+const LUA_CATCHING_FUNCTIONS: [(&str, &str); 3] = [("_G", "pcall"), ("_G", "xpcall"), ("coroutine", "resume")];
+
+// Aborts Lua code that runs past a deadline, so runaway scripts can't hang the host.
+// Shared between the runtime, which arms it, and the Lua hook, which checks it.
+#[derive(Clone)]
+pub struct Watchdog {
+    epoch: Instant,
+    deadline_ns: Arc<AtomicU64>,
+    tripped: Arc<AtomicBool>
+}
+
+impl Watchdog {
+    pub fn new() -> Watchdog {
+        Self {
+            epoch: Instant::now(),
+            deadline_ns: Arc::new(AtomicU64::new(DISARMED)),
+            tripped: Arc::new(AtomicBool::new(false))
+        }
+    }
+
+    // Once tripped, the error is raised again on every interval and after every caught error, until the call returns.
+    pub fn install(&self, lua: &Lua) -> LuaResult<()> {
+        let watchdog = self.clone();
+
+        lua.set_hook(LuaHookTriggers::new().every_nth_instruction(INSTRUCTION_INTERVAL), move |_lua, _debug| {
+            if watchdog.armed() && (watchdog.tripped() || watchdog.expired()) {
+                watchdog.tripped.store(true, Ordering::Relaxed);
+                return Err(LuaError::runtime(EXCEEDED_BUDGET_MESSAGE));
+            }
+
+            Ok(LuaVmState::Continue)
+        });
+
+        for (table_name, function_name) in LUA_CATCHING_FUNCTIONS {
+            let table: LuaTable = match lua.globals().get(table_name)? {
+                LuaValue::Table(t) => t,
+                _ => continue
+            };
+            let function: LuaFunction = match table.get(function_name)? {
+                LuaValue::Function(f) => f,
+                _ => continue
+            };
+
+            let watchdog = self.clone();
+            let wrapped = lua.create_function(move |_lua, args: LuaMultiValue| {
+                let results: LuaMultiValue = function.call(args)?;
+                if watchdog.armed() && watchdog.tripped() {
+                    return Err(LuaError::runtime(EXCEEDED_BUDGET_MESSAGE));
+                }
+
+                Ok(results)
+            })?;
+            table.set(function_name, wrapped)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn arm(&self, budget_ms: f32) {
+        let budget_ns = (f32::max(budget_ms, 0.0) * 1000000.0) as u64;
+
+        self.tripped.store(false, Ordering::Relaxed);
+        self.deadline_ns.store(self.now_ns().saturating_add(budget_ns), Ordering::Relaxed);
+    }
+
+    pub fn disarm(&self) {
+        self.deadline_ns.store(DISARMED, Ordering::Relaxed);
+    }
+
+    pub fn tripped(&self) -> bool {
+        return self.tripped.load(Ordering::Relaxed);
+    }
+
+    fn armed(&self) -> bool {
+        return self.deadline_ns.load(Ordering::Relaxed) != DISARMED;
+    }
+
+    fn expired(&self) -> bool {
+        return self.now_ns() > self.deadline_ns.load(Ordering::Relaxed);
+    }
+
+    fn now_ns(&self) -> u64 {
+        return self.epoch.elapsed().as_nanos() as u64;
+    }
+}
@@ -1,10 +1,14 @@
-use std::{ fs::{self, File}, io::{self, Write} };
-use super::{ library, module_content::ModuleContent };
+use std::{ fs::{self, File}, io::{self, Write}, time::SystemTime };
+use super::{ library::{ self, LuaFiles }, manifest::{ EntryFiles, ModuleManifest }, module_content::ModuleContent, sandbox::SandboxLevel };
 
 #[derive(Clone, PartialEq)]
 pub struct Workspace {
     pub path: String,
-    pub content: ModuleContent
+    pub content: ModuleContent,
+    pub sandbox: SandboxLevel,
+    pub manifest: Option<ModuleManifest>,
+    // Every lua file in the folder, for modules to require.
+    pub files: LuaFiles
 }
 
 impl Workspace {
@@ -14,14 +18,21 @@ impl Workspace {
             None => library::MODULE_DEFAULT.to_module_content()
         };
 
-        match Workspace::create_files(&path, &content) {
+        let manifest = ModuleManifest::for_folder(&path);
+
+        match Workspace::create_files(&path, &content, &manifest.entry) {
             Err(e) => return Err(format!("Failed to create workspace folder: {}", e)),
             Ok(_) => ()
         }
+        manifest.write_to_folder(&path)?;
+        let files = library::read_lua_files(&path);
 
         let workspace = Self {
             path: path,
-            content: content
+            content: content,
+            sandbox: SandboxLevel::Sandboxed,
+            manifest: Some(manifest),
+            files: files
         };
 
         return Ok(workspace);
@@ -31,9 +42,15 @@ impl Workspace {
         let mut workspace = Self {
             path: path,
             
-            content: library::MODULE_DEFAULT.to_module_content()
+            content: library::MODULE_DEFAULT.to_module_content(),
+            sandbox: SandboxLevel::Sandboxed,
+            manifest: None,
+            files: LuaFiles::default()
         };
 
+        // The manifest's sandbox level is only a suggestion, trusting a workspace is up to the user.
+        workspace.manifest = ModuleManifest::read_from_folder(&workspace.path)?;
+
         match workspace.read_files() {
             Err(e) => return Err(format!("Failed to load workspace from folder: {}", e)),
             Ok(_) => ()
@@ -42,7 +59,10 @@ impl Workspace {
         return Ok(workspace);
     }
 
+    // Rereads the manifest and files. Keeps the sandbox level, which may have been changed since loading.
     pub fn update(&mut self) -> Result<(), String> {
+        self.manifest = ModuleManifest::read_from_folder(&self.path)?;
+
         match self.read_files() {
             Err(e) => return Err(format!("Couldn't read from workspace: {}", e)),
             Ok(_) => ()
@@ -51,30 +71,102 @@ impl Workspace {
         Ok(())
     }
 
-    fn create_files(path: &String, content :&ModuleContent) -> io::Result<()> {
+    // Whether any of the workspace files changed after the given time.
+    pub fn modified_since(&self, time: SystemTime) -> bool {
+        return !self.changed_files(time).is_empty();
+    }
+
+    // Lua files changed after the given time, with when they changed. Includes files modules require.
+    pub fn changed_files(&self, time: SystemTime) -> Vec<(String, SystemTime)> {
+        let mut changed = Vec::new();
+
+        let mut files = self.lua_files();
+        files.push(String::from(library::MANIFEST_PATH));
+
+        for file in files {
+            let modified = fs::metadata(format!("{path}/{file}", path = &self.path, file = file)).and_then(|m| m.modified());
+
+            match modified {
+                Ok(m) => {
+                    if m > time { changed.push((file, m)); }
+                },
+                Err(_e) => ()
+            }
+        }
+
+        return changed;
+    }
+
+    // All lua files in the workspace, relative to its folder.
+    pub fn lua_files(&self) -> Vec<String> {
+        return library::lua_files(&self.path);
+    }
+
+    // Covers the sections, the manifest and every other lua file in the folder, like the ones modules require.
+    pub fn generate_hash(&self) -> u64 {
+        let entry = self.entry_files();
+        let entry_files = [entry.init, entry.reset, entry.trigger, entry.run, entry.interface];
+
+        let manifest = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::MANIFEST_PATH));
+
+        let mut extra_files: Vec<(&str, &str)> = self.files.iter()
+            .filter(|f| !entry_files.contains(f.0))
+            .map(|f| (f.0.as_str(), f.1.as_str()))
+            .collect();
+        match &manifest {
+            Ok(contents) => extra_files.push((library::MANIFEST_PATH, contents.as_str())),
+            Err(_e) => ()
+        }
+
+        return self.content.generate_hash_with_files(&extra_files);
+    }
+
+    pub fn suggested_sandbox(&self) -> Option<SandboxLevel> {
+        return self.manifest.as_ref().and_then(|m| m.sandbox_level());
+    }
+
+    // The name from the manifest, or the folder name without one.
+    pub fn name(&self) -> String {
+        return match &self.manifest {
+            Some(m) if !m.name.is_empty() => m.name.clone(),
+            _ => ModuleManifest::for_folder(&self.path).name
+        };
+    }
+
+    pub fn entry_files(&self) -> EntryFiles {
+        return match &self.manifest {
+            Some(m) => m.entry.clone(),
+            None => EntryFiles::default()
+        };
+    }
+
+    fn create_files(path: &String, content: &ModuleContent, entry: &EntryFiles) -> io::Result<()> {
         fs::create_dir_all(path)?;
 
-        let mut init_file = File::create(format!("{path}/{file}", path = path, file = library::INIT_PATH))?;
+        let mut init_file = File::create(format!("{path}/{file}", path = path, file = entry.init))?;
         init_file.write_all(content.init.as_bytes())?;
-        let mut reset_file = File::create(format!("{path}/{file}", path = path, file = library::RESET_PATH))?;
+        let mut reset_file = File::create(format!("{path}/{file}", path = path, file = entry.reset))?;
         reset_file.write_all(content.reset.as_bytes())?;
-        let mut trigger_file = File::create(format!("{path}/{file}", path = path, file = library::TRIGGER_PATH))?;
+        let mut trigger_file = File::create(format!("{path}/{file}", path = path, file = entry.trigger))?;
         trigger_file.write_all(content.trigger.as_bytes())?;       
-        let mut run_file = File::create(format!("{path}/{file}", path = path, file = library::RUN_PATH))?;
+        let mut run_file = File::create(format!("{path}/{file}", path = path, file = entry.run))?;
         run_file.write_all(content.run.as_bytes())?;
-        let mut interface_file = File::create(format!("{path}/{file}", path = path, file = library::INTERFACE_PATH))?;
+        let mut interface_file = File::create(format!("{path}/{file}", path = path, file = entry.interface))?;
         interface_file.write_all(content.interface.as_bytes())?;
 
         Ok(())
     }
 
     fn read_files(&mut self) -> io::Result<()> {
-        self.content.init = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::INIT_PATH))?;
-        self.content.reset = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::RESET_PATH))?;
-        self.content.trigger = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::TRIGGER_PATH))?;
-        self.content.run = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::RUN_PATH))?;
-        self.content.interface = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = library::INTERFACE_PATH))?;
+        let entry = self.entry_files();
+        self.files = library::read_lua_files(&self.path);
+
+        self.content.init = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = entry.init))?;
+        self.content.reset = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = entry.reset))?;
+        self.content.trigger = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = entry.trigger))?;
+        self.content.run = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = entry.run))?;
+        self.content.interface = fs::read_to_string(format!("{path}/{file}", path = &self.path, file = entry.interface))?;
 
         Ok(())
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,139 @@
+use std::{ fs, path::Path, time::SystemTime };
+use serde::{ Deserialize, Serialize };
+
+use super::{ library, manifest::ModuleManifest };
+
+pub const MAX_RECENT_WORKSPACES: usize = 10;
+
+// A workspace found by the browser, described without loading or running it.
+#[derive(Clone)]
+pub struct WorkspaceListing {
+    pub path: String,
+    pub name: String,
+    pub description: String,
+    pub modified: Option<SystemTime>
+}
+
+// Recently opened workspaces and extra folders to look for workspaces in.
+// Saved next to the workspaces, so it's shared between plugin instances.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct WorkspaceHistory {
+    #[serde(default)]
+    pub recent: Vec<String>,
+    #[serde(default)]
+    pub roots: Vec<String>
+}
+
+impl WorkspaceListing {
+    // None when the folder doesn't look like a workspace.
+    pub fn read(path: &str) -> Option<WorkspaceListing> {
+        let folder = Path::new(path);
+        let has_manifest = folder.join(library::MANIFEST_PATH).is_file();
+        if !has_manifest && !folder.join(library::INIT_PATH).is_file() {
+            return None;
+        }
+
+        // A broken manifest shouldn't hide the workspace, opening it will report the error.
+        let manifest = ModuleManifest::read_from_folder(path).ok().flatten();
+        let (name, description) = match manifest {
+            Some(m) if !m.name.is_empty() => (m.name, m.description),
+            Some(m) => (ModuleManifest::for_folder(path).name, m.description),
+            None => (ModuleManifest::for_folder(path).name, String::new())
+        };
+
+        let mut files = library::lua_files(path);
+        files.push(String::from(library::MANIFEST_PATH));
+        let modified = files.iter()
+            .filter_map(|f| fs::metadata(folder.join(f)).and_then(|m| m.modified()).ok())
+            .max();
+
+        return Some(WorkspaceListing {
+            path: String::from(path),
+            name: name,
+            description: description,
+            modified: modified
+        });
+    }
+}
+
+impl WorkspaceHistory {
+    pub fn load() -> WorkspaceHistory {
+        let contents = match fs::read_to_string(WorkspaceHistory::file_path()) {
+            Ok(c) => c,
+            Err(_e) => return WorkspaceHistory::default()
+        };
+
+        return serde_json::from_str(&contents).unwrap_or_default();
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let contents = match serde_json::to_string_pretty(self) {
+            Ok(c) => c,
+            Err(e) => return Err(format!("Couldn't save workspace history: {}", e))
+        };
+
+        match fs::create_dir_all(library::default_workspaces_path()).and_then(|_| fs::write(WorkspaceHistory::file_path(), contents)) {
+            Ok(()) => return Ok(()),
+            Err(e) => return Err(format!("Couldn't save workspace history: {}", e))
+        }
+    }
+
+    // Moves the path to the front of the recent list.
+    pub fn add_recent(&mut self, path: &str) {
+        self.recent.retain(|r| r != path);
+        self.recent.insert(0, String::from(path));
+        self.recent.truncate(MAX_RECENT_WORKSPACES);
+    }
+
+    pub fn add_root(&mut self, path: &str) {
+        if path.is_empty() || self.roots.iter().any(|r| r == path) { return; }
+
+        self.roots.push(String::from(path));
+    }
+
+    pub fn remove_root(&mut self, path: &str) {
+        self.roots.retain(|r| r != path);
+    }
+
+    // The default workspaces folder, then the ones the user added.
+    pub fn all_roots(&self) -> Vec<String> {
+        let mut roots = vec![library::default_workspaces_path()];
+        for root in &self.roots {
+            if !roots.contains(root) { roots.push(root.clone()); }
+        }
+
+        return roots;
+    }
+
+    // Lists the workspaces directly inside each root, most recently modified first.
+    pub fn scan(&self) -> Vec<WorkspaceListing> {
+        let mut listings: Vec<WorkspaceListing> = Vec::new();
+
+        for root in self.all_roots() {
+            let entries = match fs::read_dir(&root) {
+                Ok(e) => e,
+                Err(_e) => continue
+            };
+
+            for entry in entries.flatten() {
+                let path = entry.path();
+                if !path.is_dir() { continue; }
+
+                let path = path.to_string_lossy().to_string();
+                if listings.iter().any(|l| l.path == path) { continue; }
+
+                match WorkspaceListing::read(&path) {
+                    Some(l) => listings.push(l),
+                    None => ()
+                }
+            }
+        }
+
+        listings.sort_by(|a, b| b.modified.cmp(&a.modified));
+        return listings;
+    }
+
+    fn file_path() -> String {
+        return format!("{path}/{file}", path = library::default_workspaces_path(), file = library::BROWSER_PATH);
+    }
+}